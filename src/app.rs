@@ -1,70 +1,208 @@
-use crate::archive::Archive;
-use crate::format::Format;
-use crate::status::Status;
+use crate::log::{LogEntry, LogLevel};
+use crate::preferences::Preferences;
+use crate::widget::archive::archive;
 use crate::widget::button::icon_button;
 use crate::widget::icon::icon;
+use crate::widget::log::log_panel;
+use abz::archive::{Archive, OutputExists, list_archive_entry};
+use abz::cancel::{CancelToken, Cancelled};
+use abz::format::{Format, Level, NameEncoding, Target, ZipMethod};
+use abz::options::{ConflictPolicy, Filter, Options, UnsupportedPolicy};
+use abz::password::{Password, PasswordError};
+use abz::scheduler::{Scheduler, default_concurrency};
+use abz::status::Status;
 use anyhow::anyhow;
 use iced::border::Radius;
 use iced::task::sipper;
 use iced::widget::{
-    Column, Space, center, column, container, opaque, pick_list, row, scrollable, stack, text,
-    toggler,
+    Column, button, center, column, container, opaque, pick_list, row, scrollable, stack, text,
+    text_input, toggler,
+};
+use iced::{
+    Alignment, Border, Color, Element, Event, Font, Length, Padding, Settings, Size, Subscription,
+    Task, Theme, Vector, application, event, window,
 };
-use iced::{Alignment, Element, Font, Length, Padding, Settings, Task, Theme, Vector, application};
 use lucide_rs::Lucide;
-use rfd::AsyncFileDialog;
-use std::fs::{DirEntry, read_dir};
+use rfd::{AsyncFileDialog, AsyncMessageDialog, MessageButtons, MessageDialogResult, MessageLevel};
+use std::cell::Cell;
+use std::collections::VecDeque;
 use std::path::{Path, PathBuf};
-use tokio::spawn;
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::oneshot;
 use tokio::task::spawn_blocking;
 
 const TITLE: &str = "Abz";
 
 const NOTO_SANS_SC: Font = Font::with_name("Noto Sans SC");
 
+/// 工作线程等待输入密码的回复通道, 回复`None`表示不再尝试该压缩包
+type PasswordAnswer = Arc<Mutex<Option<oneshot::Sender<Option<Password>>>>>;
+
 #[derive(Debug, Clone)]
 pub enum Message {
-    UpdateFormat(Format),
+    UpdateFormat(Target),
+    UpdateOutputPassword(Password),
+    UpdateUnsupportedPolicy(UnsupportedPolicy),
+    UpdateHardlinkPolicy(UnsupportedPolicy),
+    UpdateConflictPolicy(ConflictPolicy),
     UpdateRecursion(bool),
+    UpdateMirror(bool),
+    UpdateVerify(bool),
+    UpdateCopy(bool),
+    UpdateConcurrency(usize),
+    UpdateInclude(String),
+    UpdateExclude(String),
+    UpdateNewPassword(Password),
+    AddPassword,
+    ClearPasswords,
     PickFiles,
     PickFolder,
+    PickSourceFolders,
+    FilesHovered,
+    FilesHoveredLeft,
+    FileDropped(PathBuf),
+    WindowResized(Size),
+    CloseRequested(window::Id),
+    ResetPreferences,
     SelectArchives(Vec<Archive>),
     RemoveAll,
     RemoveArchive(usize),
+    UpdateArchiveEncoding(usize, NameEncoding),
+    UpdateArchiveTarget(usize, Format),
+    UpdateBulkSource(Format),
+    UpdateBulkTarget(Format),
+    ApplyBulkTarget,
+    ResetArchiveTargets,
     Convert,
+    Test,
+    Cancel,
+    CancelArchive(usize),
     SelectSaveFolder(Option<PathBuf>),
     Completed,
     UpdateArchiveStatus(usize, Status),
+    AskPassword(usize, PasswordError, PasswordAnswer),
+    UpdatePromptPassword(Password),
+    AnswerPassword(bool),
     Error(String),
+    Log(LogEntry),
+    ToggleLogs,
+    ToggleLogEntry(usize),
+    /// 复制指定的记录, 为空时复制全部
+    CopyLog(Option<usize>),
+    ExportLog,
+    ClearLog,
+}
+
+///
+/// 等待用户输入密码的压缩包
+///
+struct PasswordPrompt {
+    index: usize,
+    error: PasswordError,
+    answer: PasswordAnswer,
+}
+
+impl PasswordPrompt {
+    fn answer(&self, password: Option<Password>) {
+        if let Some(answer) = self
+            .answer
+            .lock()
+            .expect("lock password answer error")
+            .take()
+        {
+            let _ = answer.send(password);
+        }
+    }
 }
 
 pub struct App {
-    format: Format,
+    target: Target,
+    options: Options,
+    /// 同时转换的压缩包数量
+    concurrency: usize,
     archives: Vec<Archive>,
+    /// 本次转换中每个压缩包的取消标记
+    cancels: Vec<CancelToken>,
+    /// 转换期间才扫描完成的压缩包, 转换结束后再加入列表, 避免正在转换的压缩包下标变化
+    pending: Vec<Archive>,
     loading: bool,
     recursion: bool,
+    /// 是否有文件正被拖动到窗口上
+    hovering: bool,
+    /// 批量为指定格式的源设置目标格式
+    bulk_source: Option<Format>,
+    bulk_target: Format,
+    /// 上次选择文件或文件夹的目录
+    input_dir: Option<PathBuf>,
+    /// 上次选择的保存目录
+    output_dir: Option<PathBuf>,
+    window: Size,
+    /// 最近一次写入设置文件的内容, 设置没有变化时不再写入
+    saved: Preferences,
+    logs: Vec<LogEntry>,
+    /// 是否显示日志面板
+    show_logs: bool,
+    /// 输入框中的包含/排除规则原文, 以逗号分隔
+    include: String,
+    exclude: String,
+    /// 批量处理时加密压缩包依次尝试的密码
+    passwords: Vec<Password>,
+    /// 密码列表输入框中尚未添加的密码
+    new_password: Password,
+    /// 排队等待输入密码的压缩包, 每次只显示第一个
+    prompts: VecDeque<PasswordPrompt>,
+    prompt_password: Password,
 }
 
 impl Default for App {
     fn default() -> Self {
         Self {
-            format: Default::default(),
+            target: Default::default(),
+            options: Default::default(),
+            concurrency: default_concurrency(),
             archives: vec![],
+            cancels: vec![],
+            pending: vec![],
             loading: false,
             recursion: true,
+            hovering: false,
+            bulk_source: None,
+            bulk_target: Format::default(),
+            input_dir: None,
+            output_dir: None,
+            window: Preferences::default().window,
+            saved: Preferences::default(),
+            logs: vec![],
+            show_logs: false,
+            include: String::new(),
+            exclude: String::new(),
+            passwords: vec![],
+            new_password: Password::default(),
+            prompts: VecDeque::new(),
+            prompt_password: Password::default(),
         }
     }
 }
 
 impl App {
     pub fn run() -> iced::Result {
-        application(App::new, App::update, App::view)
-            .settings(Self::settings())
-            .theme(App::theme)
-            .title(TITLE)
-            .executor::<tokio::runtime::Runtime>()
-            .run()
+        let preferences = Preferences::load();
+        let size = preferences.window;
+        application(
+            move || App::new(preferences.clone()),
+            App::update,
+            App::view,
+        )
+        .subscription(App::subscription)
+        .window_size(size)
+        // 关闭前先保存窗口大小
+        .exit_on_close_request(false)
+        .settings(Self::settings())
+        .theme(App::theme)
+        .title(TITLE)
+        .executor::<tokio::runtime::Runtime>()
+        .run()
     }
 
     fn settings() -> Settings {
@@ -82,33 +220,247 @@ impl App {
         Theme::Dracula
     }
 
-    fn new() -> (Self, Task<Message>) {
-        (Self::default(), Task::none())
+    fn new(preferences: Preferences) -> (Self, Task<Message>) {
+        let mut app = Self::default();
+        app.apply(&preferences);
+        app.saved = preferences;
+        (app, Task::none())
     }
 
+    ///
+    /// 应用保存的设置
+    ///
+    fn apply(&mut self, preferences: &Preferences) {
+        self.target = preferences.target;
+        self.recursion = preferences.recursion;
+        self.concurrency = preferences.concurrency;
+        self.options.conflict = preferences.conflict;
+        self.options.unsupported = preferences.unsupported;
+        self.options.hardlink = preferences.hardlink;
+        self.options.mirror = preferences.mirror;
+        self.options.verify = preferences.verify;
+        self.options.copy = preferences.copy;
+        self.input_dir = preferences.input_dir.clone();
+        self.output_dir = preferences.output_dir.clone();
+        self.window = preferences.window;
+        if !self.target.format.encryptable() {
+            self.options.password = None;
+        }
+    }
+
+    ///
+    /// 复制或导出全部日志时的文本, 按时间顺序每条一行, 错误链缩进显示在记录下方
+    ///
+    fn log_text(&self) -> String {
+        self.logs
+            .iter()
+            .map(LogEntry::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    ///
+    /// 当前需要保存的设置, 不包含密码
+    ///
+    fn preferences(&self) -> Preferences {
+        Preferences {
+            target: self.target,
+            recursion: self.recursion,
+            concurrency: self.concurrency,
+            conflict: self.options.conflict,
+            unsupported: self.options.unsupported,
+            hardlink: self.options.hardlink,
+            mirror: self.options.mirror,
+            verify: self.options.verify,
+            copy: self.options.copy,
+            input_dir: self.input_dir.clone(),
+            output_dir: self.output_dir.clone(),
+            window: self.window,
+        }
+    }
+
+    ///
+    /// 监听拖放到窗口上的文件、窗口大小的变化和关闭窗口
+    ///
+    fn subscription(&self) -> Subscription<Message> {
+        event::listen_with(|event, _, id| match event {
+            Event::Window(window::Event::FileHovered(_)) => Some(Message::FilesHovered),
+            Event::Window(window::Event::FilesHoveredLeft) => Some(Message::FilesHoveredLeft),
+            Event::Window(window::Event::FileDropped(path)) => Some(Message::FileDropped(path)),
+            Event::Window(window::Event::Resized(size)) => Some(Message::WindowResized(size)),
+            Event::Window(window::Event::CloseRequested) => Some(Message::CloseRequested(id)),
+            _ => None,
+        })
+    }
+
+    ///
+    /// 处理消息, 设置发生变化时写回设置文件
+    ///
+    /// 窗口大小在拖动时会连续变化, 只记录在内存中, 关闭窗口时再保存
+    ///
     fn update(&mut self, message: Message) -> Task<Message> {
+        let task = self.handle(message);
+        let preferences = Preferences {
+            window: self.saved.window,
+            ..self.preferences()
+        };
+        if preferences == self.saved {
+            return task;
+        }
+        // 保存失败时同样记录为已保存, 避免每条消息都重复报错
+        let result = preferences.save();
+        self.saved = preferences;
+        match result {
+            Ok(()) => task,
+            Err(e) => Task::batch([
+                task,
+                Task::done(Message::Error(format!("保存设置失败: {:#}", e))),
+            ]),
+        }
+    }
+
+    fn handle(&mut self, message: Message) -> Task<Message> {
         match message {
-            Message::UpdateFormat(format) => {
-                self.format = format;
+            Message::UpdateFormat(target) => {
+                self.target = target;
+                // 不支持加密的格式不显示密码输入框, 同时清除密码, 避免转换时报错
+                if !target.format.encryptable() {
+                    self.options.password = None;
+                }
+                Task::none()
+            }
+            Message::UpdateOutputPassword(password) => {
+                self.options.password = (!password.is_empty()).then_some(password);
+                // 固实压缩会把未加密的条目内容暂存到磁盘, 设置密码时关闭
+                if self.options.password.is_some() {
+                    self.target.solid = false;
+                }
+                Task::none()
+            }
+            Message::UpdateUnsupportedPolicy(policy) => {
+                self.options.unsupported = policy;
+                Task::none()
+            }
+            Message::UpdateHardlinkPolicy(policy) => {
+                self.options.hardlink = policy;
+                Task::none()
+            }
+            Message::UpdateConflictPolicy(policy) => {
+                self.options.conflict = policy;
                 Task::none()
             }
             Message::UpdateRecursion(recursion) => {
                 self.recursion = recursion;
                 Task::none()
             }
-            Message::PickFiles => Task::perform(pick_files(), |result| match result {
-                Ok(archives) => Message::SelectArchives(archives),
-                Err(e) => Message::Error(e.to_string()),
-            }),
-            Message::PickFolder => {
-                Task::perform(pick_folder(self.recursion), |result| match result {
+            Message::UpdateMirror(mirror) => {
+                self.options.mirror = mirror;
+                Task::none()
+            }
+            Message::UpdateVerify(verify) => {
+                self.options.verify = verify;
+                Task::none()
+            }
+            Message::UpdateCopy(copy) => {
+                self.options.copy = copy;
+                Task::none()
+            }
+            Message::UpdateConcurrency(concurrency) => {
+                self.concurrency = concurrency;
+                Task::none()
+            }
+            Message::UpdateInclude(include) => {
+                self.options.filter.include = Filter::split(&include);
+                self.include = include;
+                Task::none()
+            }
+            Message::UpdateExclude(exclude) => {
+                self.options.filter.exclude = Filter::split(&exclude);
+                self.exclude = exclude;
+                Task::none()
+            }
+            Message::UpdateNewPassword(password) => {
+                self.new_password = password;
+                Task::none()
+            }
+            Message::AddPassword => {
+                let password = std::mem::take(&mut self.new_password);
+                if !password.is_empty() && !self.passwords.contains(&password) {
+                    self.passwords.push(password);
+                }
+                Task::none()
+            }
+            Message::ClearPasswords => {
+                self.passwords.clear();
+                Task::none()
+            }
+            Message::PickFiles => {
+                Task::perform(pick_files(self.input_dir.clone()), |result| match result {
                     Ok(archives) => Message::SelectArchives(archives),
-                    Err(e) => Message::Error(e.to_string()),
+                    Err(e) => Message::Error(format!("{:#}", e)),
                 })
             }
+            Message::PickFolder => Task::perform(
+                pick_folder(self.recursion, self.input_dir.clone()),
+                |result| match result {
+                    Ok(archives) => Message::SelectArchives(archives),
+                    Err(e) => Message::Error(format!("{:#}", e)),
+                },
+            ),
+            Message::PickSourceFolders => Task::perform(
+                pick_source_folders(self.input_dir.clone()),
+                |result| match result {
+                    Ok(archives) => Message::SelectArchives(archives),
+                    Err(e) => Message::Error(format!("{:#}", e)),
+                },
+            ),
+            Message::FilesHovered => {
+                self.hovering = !self.loading;
+                Task::none()
+            }
+            Message::FilesHoveredLeft => {
+                self.hovering = false;
+                Task::none()
+            }
+            // 每个拖放的文件或目录单独产生一个事件, 转换期间忽略
+            Message::FileDropped(path) => {
+                self.hovering = false;
+                if self.loading {
+                    return Task::none();
+                }
+                let recursion = self.recursion;
+                Task::perform(
+                    async move {
+                        match path.is_dir() {
+                            true => scan_folder(&path, recursion),
+                            false => Ok(vec![parse_or_unpacked(&path)?]),
+                        }
+                    },
+                    |result| match result {
+                        Ok(archives) => Message::SelectArchives(archives),
+                        Err(e) => Message::Error(format!("{:#}", e)),
+                    },
+                )
+            }
             Message::SelectArchives(archives) => {
-                self.archives.extend(archives);
-                self.archives.sort_by_key(|archive| archive.status.clone());
+                for archive in &archives {
+                    if let Some(warning) = archive.mislabeled_warning() {
+                        self.logs.push(LogEntry::warning(warning));
+                    }
+                }
+                // 记住输入所在的目录, 下次打开对话框时从该目录开始
+                if let Some(archive) = archives.first() {
+                    self.input_dir = archive
+                        .root
+                        .clone()
+                        .or_else(|| archive.path.parent().map(Path::to_path_buf));
+                }
+                if self.loading {
+                    self.pending.extend(archives);
+                } else {
+                    self.archives.extend(archives);
+                    self.archives.sort_by_key(|archive| archive.status.clone());
+                }
                 Task::none()
             }
             Message::RemoveArchive(index) => {
@@ -116,84 +468,222 @@ impl App {
                 self.archives.sort_by_key(|archive| archive.size);
                 Task::none()
             }
+            Message::UpdateArchiveEncoding(index, encoding) => {
+                self.archives[index].encoding = encoding;
+                Task::none()
+            }
+            // 与整批的格式相同时不再单独指定, 之后跟随整批的格式变化
+            Message::UpdateArchiveTarget(index, format) => {
+                self.archives[index].target = (format != self.target.format).then_some(format);
+                Task::none()
+            }
+            Message::UpdateBulkSource(format) => {
+                self.bulk_source = Some(format);
+                Task::none()
+            }
+            Message::UpdateBulkTarget(format) => {
+                self.bulk_target = format;
+                Task::none()
+            }
+            Message::ApplyBulkTarget => {
+                let target = (self.bulk_target != self.target.format).then_some(self.bulk_target);
+                self.archives
+                    .iter_mut()
+                    .filter(|archive| Some(archive.format) == self.bulk_source)
+                    .for_each(|archive| archive.target = target);
+                Task::none()
+            }
+            Message::ResetArchiveTargets => {
+                self.archives
+                    .iter_mut()
+                    .for_each(|archive| archive.target = None);
+                Task::none()
+            }
             Message::RemoveAll => {
                 self.archives.clear();
                 Task::none()
             }
-            Message::Convert => Task::perform(pick_save_folder(), Message::SelectSaveFolder),
+            Message::Convert => {
+                // 单独指定的目标格式不支持加密时在开始前拒绝, 避免这些压缩包在转换时逐个失败
+                let unencryptable = self.options.password.as_ref().and_then(|_| {
+                    self.archives
+                        .iter()
+                        .find(|archive| archive.target.is_some_and(|format| !format.encryptable()))
+                });
+                if let Some(archive) = unencryptable {
+                    self.show_logs = true;
+                    return Task::done(Message::Error(format!(
+                        "{}: 目标格式{}不支持加密, 请清除输出密码或修改该压缩包的目标格式",
+                        archive.path.display(),
+                        archive.target.unwrap_or(self.target.format)
+                    )));
+                }
+                Task::perform(
+                    pick_save_folder(self.output_dir.clone()),
+                    Message::SelectSaveFolder,
+                )
+            }
+            // Windows下最小化时窗口大小为0, 不保存
+            Message::WindowResized(size) => {
+                if size.width > 0. && size.height > 0. {
+                    self.window = size;
+                }
+                Task::none()
+            }
+            Message::CloseRequested(id) => {
+                let preferences = self.preferences();
+                if preferences != self.saved {
+                    // 窗口即将关闭, 保存失败时已无法提示
+                    let _ = preferences.save();
+                    self.saved = preferences;
+                }
+                window::close(id)
+            }
+            // 只恢复设置, 不影响已选择的压缩包和密码列表
+            Message::ResetPreferences => {
+                let preferences = Preferences::default();
+                self.apply(&preferences);
+                window::latest().and_then(move |id| window::resize(id, preferences.window))
+            }
+            Message::Test => self.run_batch(|archive, worker| {
+                archive.test(worker.cancel, |status| worker.status(status))?;
+                Ok(Status::Success)
+            }),
+            Message::Cancel => {
+                self.cancels.iter().for_each(CancelToken::cancel);
+                self.prompts
+                    .drain(..)
+                    .for_each(|prompt| prompt.answer(None));
+                self.archives
+                    .iter_mut()
+                    .filter(|archive| archive.status == Status::Pending)
+                    .for_each(|archive| archive.status = Status::Cancelled);
+                Task::none()
+            }
+            Message::CancelArchive(index) => {
+                if let Some(cancel) = self.cancels.get(index) {
+                    cancel.cancel();
+                }
+                self.prompts.retain(|prompt| {
+                    let cancelled = prompt.index == index;
+                    if cancelled {
+                        prompt.answer(None);
+                    }
+                    !cancelled
+                });
+                // 排队中的压缩包不会再被处理, 直接显示为已取消; 处理中的等待任务自行结束
+                if self.archives[index].status == Status::Pending {
+                    self.archives[index].status = Status::Cancelled;
+                }
+                Task::none()
+            }
             Message::SelectSaveFolder(folder) => match folder {
                 Some(folder) => {
-                    self.loading = true;
-                    let format = self.format;
-                    let archives = self.archives.clone();
-                    let sipper = sipper(move |mut sender| async move {
-                        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
-
-                        fn send_status(
-                            tx: &UnboundedSender<(usize, Status)>,
-                            index: usize,
-                            status: Status,
-                        ) {
-                            tx.send((index, status)).expect("send archive status error")
-                        }
-
-                        archives
-                            .into_iter()
-                            .enumerate()
-                            .for_each(|(index, archive)| {
-                                let folder = folder.clone();
-                                let tx = tx.clone();
-                                send_status(&tx, index, Status::Processing(0.));
-                                // 在单独的线程中执行转换任务
-                                spawn(async move {
-                                    let _tx = tx.clone();
-                                    match spawn_blocking(move || {
-                                        archive.convert(folder, format, |ratio| {
-                                            send_status(&_tx, index, Status::Processing(ratio));
-                                        })
-                                    })
-                                    .await
-                                    {
-                                        Ok(result) => match result {
-                                            Ok(_) => send_status(&tx, index, Status::Success),
-                                            Err(e) => send_status(
-                                                &tx,
-                                                index,
-                                                Status::Failed(e.to_string().into()),
-                                            ),
-                                        },
-                                        Err(e) => send_status(
-                                            &tx,
-                                            index,
-                                            Status::Failed(e.to_string().into()),
-                                        ),
-                                    }
-                                });
-                            });
-                        // 这里需要手动释放 tx，否则会阻塞。
-                        drop(tx);
-                        while let Some((index, status)) = rx.recv().await {
-                            sender.send((index, status)).await;
+                    self.output_dir = Some(folder.clone());
+                    let target = self.target;
+                    let options = self.options.clone();
+                    self.run_batch(move |archive, worker| {
+                        let mut options = options.clone();
+                        loop {
+                            let result = archive.convert(
+                                folder.clone(),
+                                archive.resolve_target(target),
+                                &options,
+                                worker.cancel,
+                                |status| worker.status(status),
+                            );
+                            let exists = result
+                                .as_ref()
+                                .err()
+                                .and_then(|e| e.downcast_ref::<OutputExists>())
+                                .map(|exists| exists.0.clone());
+                            match exists {
+                                Some(path) if options.conflict == ConflictPolicy::Ask => {
+                                    options.conflict = worker.ask_conflict(path);
+                                }
+                                _ => break result.map(|report| Status::from(&report)),
+                            }
                         }
-                    });
-                    Task::sip(
-                        sipper,
-                        |(index, status)| Message::UpdateArchiveStatus(index, status),
-                        |_| Message::Completed,
-                    )
+                    })
                 }
                 None => Task::none(),
             },
             Message::Completed => {
                 self.loading = false;
+                self.cancels.clear();
+                if !self.pending.is_empty() {
+                    self.archives.append(&mut self.pending);
+                    self.archives.sort_by_key(|archive| archive.status.clone());
+                }
                 Task::none()
             }
             Message::UpdateArchiveStatus(index, status) => {
                 self.archives[index].status = status;
                 Task::none()
             }
+            Message::AskPassword(index, error, answer) => {
+                self.prompts.push_back(PasswordPrompt {
+                    index,
+                    error,
+                    answer,
+                });
+                Task::none()
+            }
+            Message::UpdatePromptPassword(password) => {
+                self.prompt_password = password;
+                Task::none()
+            }
+            Message::AnswerPassword(submit) => {
+                let password = std::mem::take(&mut self.prompt_password);
+                if let Some(prompt) = self.prompts.pop_front() {
+                    // 记住输入的密码, 再次处理时不用重新输入
+                    if submit {
+                        self.archives[prompt.index].passwords = vec![password.clone()];
+                    }
+                    prompt.answer(submit.then_some(password));
+                }
+                Task::none()
+            }
             Message::Error(e) => {
-                eprintln!("{}", e);
+                self.logs.push(LogEntry::error(e));
+                Task::none()
+            }
+            Message::Log(entry) => {
+                self.logs.push(entry);
+                Task::none()
+            }
+            Message::ToggleLogs => {
+                self.show_logs = !self.show_logs;
+                Task::none()
+            }
+            Message::ToggleLogEntry(index) => {
+                if let Some(entry) = self.logs.get_mut(index) {
+                    entry.expanded = !entry.expanded;
+                }
+                Task::none()
+            }
+            Message::CopyLog(index) => {
+                let content = match index {
+                    Some(index) => self.logs.get(index).map(LogEntry::to_string),
+                    None => Some(self.log_text()),
+                };
+                match content {
+                    Some(content) => iced::clipboard::write(content),
+                    None => Task::none(),
+                }
+            }
+            Message::ExportLog => {
+                Task::future(export_log(self.log_text())).then(|result| match result {
+                    Ok(Some(path)) => Task::done(Message::Log(LogEntry::info(format!(
+                        "日志已导出到 {}",
+                        path.display()
+                    )))),
+                    Ok(None) => Task::none(),
+                    Err(e) => Task::done(Message::Error(format!("导出日志失败: {:#}", e))),
+                })
+            }
+            Message::ClearLog => {
+                self.logs.clear();
                 Task::none()
             }
         }
@@ -202,17 +692,165 @@ impl App {
     fn view(&self) -> Element<'_, Message> {
         let controls = self.controls();
         let archives = self.archives();
-        let main = column![controls, archives]
+        let mut main = column![controls, archives]
             .width(Length::Fill)
             .height(Length::Fill)
             .spacing(10);
-        container(main)
+        if self.show_logs {
+            main = main.push(
+                container(log_panel(&self.logs))
+                    .style(container_style)
+                    .padding(10)
+                    .width(Length::Fill),
+            );
+        }
+        let main = container(main)
             .width(Length::Fill)
             .height(Length::Fill)
             .align_y(Alignment::Start)
             .align_x(Alignment::Start)
-            .padding(10)
-            .into()
+            .padding(10);
+        match self.prompts.front() {
+            Some(prompt) => stack![main, self.prompt(prompt)].into(),
+            None => main.into(),
+        }
+    }
+
+    ///
+    /// 输入密码的对话框, 覆盖在主界面之上
+    ///
+    fn prompt(&self, prompt: &PasswordPrompt) -> Element<'_, Message> {
+        let name = self.archives[prompt.index]
+            .path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let hint = match prompt.error {
+            PasswordError::Required => format!("{} 已加密, 请输入密码", name),
+            PasswordError::Wrong => format!("{} 的密码错误, 请重新输入", name),
+        };
+        let dialog = container(
+            column![
+                text(hint),
+                text_input("密码", self.prompt_password.as_str())
+                    .secure(true)
+                    .on_input(|password| Message::UpdatePromptPassword(password.into()))
+                    .on_submit(Message::AnswerPassword(true)),
+                row![
+                    icon_button(Lucide::Check, "确定")
+                        .width(Length::Fixed(80.))
+                        .on_press(Message::AnswerPassword(true)),
+                    icon_button(Lucide::X, "跳过")
+                        .width(Length::Fixed(80.))
+                        .on_press(Message::AnswerPassword(false)),
+                ]
+                .spacing(10),
+            ]
+            .spacing(10)
+            .align_x(Alignment::End),
+        )
+        .style(container_style)
+        .padding(20)
+        .width(Length::Fixed(360.));
+        opaque(center(dialog).style(|_| container::background(Color::from_rgba(0., 0., 0., 0.6))))
+    }
+
+    ///
+    /// 本次处理的压缩包, 密码列表追加在各自已知的密码之后
+    ///
+    fn batch(&self) -> Vec<Archive> {
+        self.archives
+            .iter()
+            .cloned()
+            .map(|mut archive| {
+                archive.passwords.extend(self.passwords.iter().cloned());
+                archive
+            })
+            .collect()
+    }
+
+    ///
+    /// 在后台按并发数量处理本次的所有压缩包, 检测和转换共用,
+    /// 统一处理取消、密码输入、输出冲突的询问以及状态和日志的转发
+    ///
+    /// # Arguments
+    ///
+    /// * `job`: 在工作线程中处理单个压缩包, 返回处理完成后的状态
+    ///
+    /// returns: Task<Message>
+    ///
+    fn run_batch(
+        &mut self,
+        job: impl Fn(&Archive, &Worker) -> anyhow::Result<Status> + Send + Sync + 'static,
+    ) -> Task<Message> {
+        self.loading = true;
+        self.archives
+            .iter_mut()
+            .for_each(|archive| archive.status = Status::Pending);
+        let concurrency = self.concurrency;
+        let archives = self.batch();
+        self.cancels = archives.iter().map(|_| CancelToken::default()).collect();
+        let cancels = self.cancels.clone();
+        let sipper = sipper(move |mut sender| async move {
+            let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+            // 冲突策略为询问时, 工作线程通过该通道请求用户选择
+            let (conflict_tx, mut conflict_rx) = tokio::sync::mpsc::unbounded_channel();
+            let (password_tx, mut password_rx) = tokio::sync::mpsc::unbounded_channel();
+
+            // 在单独的线程中按顺序调度任务, 排队中的压缩包保持等待状态,
+            // 全部完成后发送端随闭包一起释放, 接收端随之结束
+            spawn_blocking(move || {
+                Scheduler::new(concurrency).run(archives, |index, archive| {
+                    let worker = Worker {
+                        index,
+                        cancel: &cancels[index],
+                        tx: &tx,
+                        conflict: &conflict_tx,
+                        answer: Cell::new(None),
+                    };
+                    if worker.cancel.is_cancelled() {
+                        worker.status(Status::Cancelled);
+                        return;
+                    }
+                    worker.status(Status::Processing(0.));
+                    let result = unlock(&archive, index, worker.cancel, &password_tx, |archive| {
+                        job(archive, &worker)
+                    });
+                    let status = match result {
+                        Ok(status) => status,
+                        Err(e) if e.is::<Cancelled>() => Status::Cancelled,
+                        Err(e) if e.is::<OutputExists>() => Status::Skipped(e.to_string().into()),
+                        Err(e) => {
+                            send_log(&tx, LogEntry::failure(&archive.path, &e));
+                            Status::Failed(format!("{:#}", e).into())
+                        }
+                    };
+                    if let Status::Warning(warning) = &status {
+                        send_log(
+                            &tx,
+                            LogEntry::warning(format!("{}: {}", archive.path.display(), warning)),
+                        );
+                    }
+                    worker.status(status);
+                });
+            });
+            loop {
+                tokio::select! {
+                    Some(message) = rx.recv() => {
+                        sender.send(message).await;
+                    }
+                    Some((path, answer)) = conflict_rx.recv() => {
+                        let _ = answer.send(ask_conflict(&path).await);
+                    }
+                    Some((index, error, answer)) = password_rx.recv() => {
+                        let answer = Arc::new(Mutex::new(Some(answer)));
+                        sender.send(Message::AskPassword(index, error, answer)).await;
+                    }
+                    else => break,
+                }
+            }
+        });
+        Task::sip(sipper, |message| message, |_| Message::Completed)
     }
 
     fn controls(&self) -> Element<'_, Message> {
@@ -231,32 +869,172 @@ impl App {
                 } else {
                     Some(Message::PickFolder)
                 }),
+            icon_button(Lucide::FolderOpen, "打包文件夹")
+                .width(Length::Fixed(110.))
+                .on_press_maybe(if self.loading {
+                    None
+                } else {
+                    Some(Message::PickSourceFolders)
+                }),
             row![
                 text("递归目录: "),
                 toggler(self.recursion).on_toggle(Message::UpdateRecursion)
             ]
             .width(Length::Fixed(100.))
+            .align_y(Alignment::Center),
+            row![
+                text("保留目录结构: "),
+                toggler(self.options.mirror).on_toggle(Message::UpdateMirror)
+            ]
+            .width(Length::Fixed(130.))
+            .align_y(Alignment::Center),
+            row![
+                text("转换后校验: "),
+                toggler(self.options.verify).on_toggle(Message::UpdateVerify)
+            ]
+            .width(Length::Fixed(120.))
+            .align_y(Alignment::Center),
+            // 默认同格式也按目标的压缩参数重新压缩, 开启后直接复制
+            row![
+                text("同格式仅复制: "),
+                toggler(self.options.copy).on_toggle(Message::UpdateCopy)
+            ]
+            .width(Length::Fixed(130.))
             .align_y(Alignment::Center)
         ]
         .spacing(10)
         .align_y(Alignment::Center)
         .width(Length::Fill);
 
-        let formats = row![
+        // 切换格式或压缩方法时级别范围会变化, 级别重置为默认
+        let target = self.target;
+        let mut formats = row![
             icon(Lucide::Torus),
             text("目标格式:"),
-            pick_list(Format::ALL, Some(self.format), Message::UpdateFormat)
+            pick_list(Format::TARGETS, Some(target.format), move |format| {
+                Message::UpdateFormat(Target {
+                    format,
+                    level: Level::Default,
+                    ..target
+                })
+            })
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center);
+        if target.format == Format::Zip {
+            formats = formats.push(pick_list(
+                ZipMethod::ALL,
+                Some(target.method),
+                move |method| {
+                    Message::UpdateFormat(Target {
+                        method,
+                        level: Level::Default,
+                        ..target
+                    })
+                },
+            ));
+        }
+        if let Some(levels) = target.levels() {
+            let levels = std::iter::once(Level::Default)
+                .chain(levels.map(Level::Custom))
+                .collect::<Vec<_>>();
+            formats = formats.push(text("级别:")).push(pick_list(
+                levels,
+                Some(target.level),
+                move |level| Message::UpdateFormat(Target { level, ..target }),
+            ));
+        }
+
+        #[cfg(feature = "7z")]
+        if target.format == Format::SevenZ {
+            // 固实压缩会把未加密的条目内容暂存到磁盘, 不能与加密同时使用
+            let encrypted = self.options.password.is_some();
+            formats =
+                formats
+                    .push(text(if encrypted {
+                        "固实(加密时不可用):"
+                    } else {
+                        "固实:"
+                    }))
+                    .push(
+                        toggler(target.solid).on_toggle_maybe((!encrypted).then_some(
+                            move |solid| Message::UpdateFormat(Target { solid, ..target }),
+                        )),
+                    );
+        }
+        if target.format.encryptable() {
+            let password = self
+                .options
+                .password
+                .as_ref()
+                .map(Password::as_str)
+                .unwrap_or_default();
+            formats = formats.push(icon(Lucide::Lock)).push(
+                text_input("加密密码, 留空不加密", password)
+                    .secure(true)
+                    .on_input(|password| Message::UpdateOutputPassword(password.into()))
+                    .width(Length::Fixed(150.)),
+            );
+        }
+
+        let unsupported = row![
+            icon(Lucide::Link),
+            text("不支持的条目:"),
+            pick_list(
+                UnsupportedPolicy::ALL,
+                Some(self.options.unsupported),
+                Message::UpdateUnsupportedPolicy
+            ),
+            text("硬链接:"),
+            pick_list(
+                UnsupportedPolicy::ALL,
+                Some(self.options.hardlink),
+                Message::UpdateHardlinkPolicy
+            )
         ]
         .spacing(5)
         .align_y(Alignment::Center);
 
+        let conflict = row![
+            text("同名文件:"),
+            pick_list(
+                ConflictPolicy::ALL,
+                Some(self.options.conflict),
+                Message::UpdateConflictPolicy
+            )
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center);
+
+        // 最多允许设置为CPU核心数的两倍, 适合IO较慢的场景
+        let concurrency = row![
+            text("并发:"),
+            pick_list(
+                (1..=default_concurrency() * 2).collect::<Vec<_>>(),
+                Some(self.concurrency),
+                Message::UpdateConcurrency
+            )
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center);
+
+        // 转换期间将转换按钮替换为取消按钮
         let actions = row![
-            icon_button(Lucide::Play, "转换")
+            if self.loading {
+                icon_button(Lucide::Square, "取消")
+                    .width(Length::Fixed(80.))
+                    .on_press(Message::Cancel)
+            } else {
+                icon_button(Lucide::Play, "转换")
+                    .width(Length::Fixed(80.))
+                    .on_press(Message::Convert)
+            },
+            icon_button(Lucide::ListChecks, "测试")
                 .width(Length::Fixed(80.))
-                .on_press_maybe(if self.loading {
+                .on_press_maybe(if self.loading || self.archives.is_empty() {
                     None
                 } else {
-                    Some(Message::Convert)
+                    Some(Message::Test)
                 }),
             icon_button(Lucide::Trash, "清空")
                 .width(Length::Fixed(80.))
@@ -265,18 +1043,107 @@ impl App {
                 } else {
                     Some(Message::RemoveAll)
                 }),
+            // 显示错误数量, 有新的失败时不用打开面板也能注意到
+            button(
+                row![
+                    icon(Lucide::ScrollText),
+                    text(format!(
+                        "日志({})",
+                        self.logs
+                            .iter()
+                            .filter(|entry| entry.level == LogLevel::Error)
+                            .count()
+                    ))
+                ]
+                .spacing(5)
+            )
+            .on_press(Message::ToggleLogs),
+            icon_button(Lucide::RotateCcw, "恢复默认设置")
+                .width(Length::Fixed(120.))
+                .on_press_maybe(if self.loading {
+                    None
+                } else {
+                    Some(Message::ResetPreferences)
+                }),
         ]
         .spacing(10)
         .align_y(Alignment::Center);
 
+        let settings = row![formats, unsupported, conflict, concurrency]
+            .spacing(20)
+            .align_y(Alignment::Center);
+
+        // 规则对所有源生效, 以逗号分隔, 例如`*.tmp, .git`
+        let filters = row![
+            text("包含:"),
+            text_input("全部", &self.include).on_input(Message::UpdateInclude),
+            text("排除:"),
+            text_input("例如 *.tmp, .git", &self.exclude).on_input(Message::UpdateExclude),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center);
+
+        // 只列出当前列表中出现的源格式, 例如把所有7z设置为zip
+        let sources = Format::TARGETS
+            .iter()
+            .filter(|format| {
+                self.archives
+                    .iter()
+                    .any(|archive| archive.format == **format)
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        let bulk = row![
+            text("批量设置:"),
+            pick_list(sources, self.bulk_source, Message::UpdateBulkSource).placeholder("源格式"),
+            text("转换为"),
+            pick_list(
+                Format::TARGETS,
+                Some(self.bulk_target),
+                Message::UpdateBulkTarget
+            ),
+            icon_button(Lucide::Check, "应用").on_press_maybe(
+                self.bulk_source
+                    .is_some()
+                    .then_some(Message::ApplyBulkTarget)
+            ),
+            icon_button(Lucide::RotateCcw, "全部使用目标格式").on_press_maybe(
+                self.archives
+                    .iter()
+                    .any(|archive| archive.target.is_some())
+                    .then_some(Message::ResetArchiveTargets)
+            ),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center);
+
+        // 加密的压缩包依次尝试列表中的密码, 都不正确时再弹出对话框询问
+        let passwords = row![
+            icon(Lucide::KeyRound),
+            text("密码列表:"),
+            text_input("输入后回车添加", self.new_password.as_str())
+                .secure(true)
+                .on_input(|password| Message::UpdateNewPassword(password.into()))
+                .on_submit(Message::AddPassword)
+                .width(Length::Fixed(200.)),
+            text(format!("已添加{}个", self.passwords.len())),
+            icon_button(Lucide::X, "清空密码")
+                .on_press_maybe((!self.passwords.is_empty()).then_some(Message::ClearPasswords)),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center);
+
         container(
-            row![opens, formats, actions]
-                .spacing(10)
-                .align_y(Alignment::Center),
+            column![
+                row![opens, actions].spacing(10).align_y(Alignment::Center),
+                settings,
+                filters,
+                row![bulk, passwords].spacing(20).align_y(Alignment::Center)
+            ]
+            .spacing(10),
         )
         .style(container_style)
-        .padding(Padding::default().left(10).right(10))
-        .height(Length::Fixed(50.))
+        .padding(10)
         .width(Length::Fill)
         .align_x(Alignment::Center)
         .align_y(Alignment::Center)
@@ -284,117 +1151,251 @@ impl App {
     }
 
     fn archives(&self) -> Element<'_, Message> {
-        if self.archives.is_empty() {
-            center(text("未选中任何文件"))
-        } else {
-            let items = Column::with_children(
-                self.archives
-                    .iter()
-                    .enumerate()
-                    .map(|(index, archive)| archive.view(index)),
-            )
-            .padding(Padding::default())
-            .spacing(10);
+        let archives =
+            if self.archives.is_empty() {
+                center(text("未选中任何文件"))
+            } else {
+                let items =
+                    Column::with_children(self.archives.iter().enumerate().map(|(index, item)| {
+                        archive(item, index, self.loading, self.target.format)
+                    }))
+                    .padding(Padding::default())
+                    .spacing(10);
 
-            container(
-                scrollable(match self.loading {
-                    true => mask_layer(items),
-                    false => items.into(),
-                })
-                .spacing(10),
-            )
-            .padding(10)
+                container(
+                    // 转换期间行内只保留取消按钮, 不再需要遮罩层
+                    scrollable(items).spacing(10),
+                )
+                .padding(10)
+            }
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .style(container_style);
+        if !self.hovering {
+            return archives.into();
         }
-        .width(Length::Fill)
-        .height(Length::Fill)
-        .style(container_style)
-        .into()
+        // 拖动文件经过窗口时高亮列表区域, 提示可以松开添加
+        let highlight = center(text("松开鼠标以添加压缩包或文件夹").size(16))
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .style(|theme: &Theme| {
+                let palette = theme.extended_palette();
+                container::Style {
+                    background: Some(palette.primary.weak.color.scale_alpha(0.3).into()),
+                    border: Border::default()
+                        .width(2)
+                        .rounded(5)
+                        .color(palette.primary.strong.color),
+                    ..container::Style::default()
+                }
+            });
+        stack![archives, highlight].into()
     }
 }
 
-async fn pick_files() -> anyhow::Result<Vec<Archive>> {
-    AsyncFileDialog::default()
-        .add_filter("Archive Files", &Format::extensions())
-        .add_filter("Zip", &[Format::Zip.extension()])
-        .add_filter("Tar.gz", &[Format::TarGz.extension()])
+///
+/// 创建文件对话框, 从上次使用的目录开始
+///
+fn dialog(directory: Option<PathBuf>) -> AsyncFileDialog {
+    match directory {
+        Some(directory) => AsyncFileDialog::default().set_directory(directory),
+        None => AsyncFileDialog::default(),
+    }
+}
+
+async fn pick_files(directory: Option<PathBuf>) -> anyhow::Result<Vec<Archive>> {
+    Format::ALL
+        .iter()
+        .fold(
+            dialog(directory).add_filter("Archive Files", &Format::extensions()),
+            |dialog, format| dialog.add_filter(format.to_string(), format.suffixes()),
+        )
+        .add_filter("All Files", &["*"])
         .pick_files()
         .await
         .into_iter()
         .flatten()
-        .map(|handle| Archive::parse(handle.path()))
-        .try_fold(vec![], |mut archives, archive| match archive {
-            Ok(Some(archive)) => {
-                archives.push(archive);
-                Ok(archives)
-            }
-            Ok(None) => Ok(archives),
-            Err(e) => Err(e),
-        })
-        .map_err(|e| anyhow!(e))
+        .map(|handle| parse_or_unpacked(handle.path()))
+        .collect()
 }
 
-async fn pick_folder(recursion: bool) -> anyhow::Result<Vec<Archive>> {
-    match AsyncFileDialog::default().pick_folder().await {
+///
+/// 解析单独选择的文件, 不是压缩包时作为源打包
+///
+fn parse_or_unpacked(path: &Path) -> anyhow::Result<Archive> {
+    match Archive::parse(path)? {
+        Some(archive) => Ok(archive),
+        None => Archive::unpacked(path),
+    }
+}
+
+///
+/// 选择作为源打包的目录, 可以多选
+///
+async fn pick_source_folders(directory: Option<PathBuf>) -> anyhow::Result<Vec<Archive>> {
+    dialog(directory)
+        .pick_folders()
+        .await
+        .unwrap_or_default()
+        .iter()
+        .map(|folder| Archive::unpacked(folder.path()))
+        .collect()
+}
+
+async fn pick_folder(recursion: bool, directory: Option<PathBuf>) -> anyhow::Result<Vec<Archive>> {
+    match dialog(directory).pick_folder().await {
         None => Ok(vec![]),
-        Some(folder) => {
-            let entries = list_archive_entry(folder.path(), recursion)?;
-            entries
-                .into_iter()
-                .try_fold(vec![], |mut items, entry| {
-                    match Archive::parse(entry.path()) {
-                        Ok(file) => {
-                            if let Some(file) = file {
-                                items.push(file);
-                            }
-                            Ok(items)
-                        }
-                        Err(e) => Err(e),
-                    }
-                })
-                .map_err(|e| anyhow!(e))
-        }
+        Some(folder) => scan_folder(folder.path(), recursion),
     }
 }
 
-fn list_archive_entry(folder: &Path, recursion: bool) -> anyhow::Result<Vec<DirEntry>> {
-    let entries = read_dir(folder).map_err(|e| anyhow!(e))?;
-    match recursion {
-        true => entries
-            .into_iter()
-            .try_fold(vec![], |mut dirs, entry| match entry {
-                Ok(entry) => match entry.file_type() {
-                    Ok(file_type) => {
-                        if file_type.is_dir() {
-                            match list_archive_entry(&entry.path(), recursion) {
-                                Ok(sub_dirs) => {
-                                    dirs.extend(sub_dirs);
-                                    Ok(dirs)
-                                }
-                                Err(e) => Err(anyhow!(e)),
-                            }
-                        } else {
-                            dirs.push(entry);
-                            Ok(dirs)
-                        }
+///
+/// 查找目录中的压缩包, 以该目录作为扫描根目录
+///
+fn scan_folder(folder: &Path, recursion: bool) -> anyhow::Result<Vec<Archive>> {
+    let entries = list_archive_entry(folder, recursion)?;
+    entries
+        .into_iter()
+        .try_fold(vec![], |mut items, entry| {
+            match Archive::parse(entry.path()) {
+                Ok(file) => {
+                    if let Some(file) = file {
+                        items.push(file.with_root(folder));
                     }
-                    Err(e) => Err(anyhow!(e)),
-                },
-                Err(e) => Err(anyhow!(e)),
-            }),
-        false => entries
-            .into_iter()
-            .try_fold(vec![], |mut entries, entry| match entry {
-                Ok(entry) => {
-                    entries.push(entry);
-                    Ok(entries)
+                    Ok(items)
                 }
-                Err(e) => Err(anyhow!(e)),
-            }),
+                Err(e) => Err(e),
+            }
+        })
+        .map_err(|e| anyhow!(e))
+}
+
+///
+/// 工作线程中处理单个压缩包时使用的上下文
+///
+struct Worker<'a> {
+    index: usize,
+    cancel: &'a CancelToken,
+    tx: &'a UnboundedSender<Message>,
+    conflict: &'a UnboundedSender<(PathBuf, oneshot::Sender<ConflictPolicy>)>,
+    /// 用户对输出冲突的选择, 输入密码后重试时沿用, 不再重复询问
+    answer: Cell<Option<ConflictPolicy>>,
+}
+
+impl Worker<'_> {
+    fn status(&self, status: Status) {
+        send_status(self.tx, self.index, status)
+    }
+
+    ///
+    /// 请求用户选择输出文件已存在时的处理方式, 对话框被关闭时跳过
+    ///
+    fn ask_conflict(&self, path: PathBuf) -> ConflictPolicy {
+        if let Some(answer) = self.answer.get() {
+            return answer;
+        }
+        let (answer_tx, answer_rx) = oneshot::channel();
+        let _ = self.conflict.send((path, answer_tx));
+        let answer = answer_rx.blocking_recv().unwrap_or(ConflictPolicy::Skip);
+        self.answer.set(Some(answer));
+        answer
     }
 }
 
-async fn pick_save_folder() -> Option<PathBuf> {
-    AsyncFileDialog::default()
+///
+/// 输出文件已存在时询问用户如何处理, 关闭对话框视为跳过
+///
+async fn ask_conflict(path: &Path) -> ConflictPolicy {
+    let result = AsyncMessageDialog::new()
+        .set_level(MessageLevel::Warning)
+        .set_title("目标文件已存在")
+        .set_description(format!("{} 已存在, 请选择处理方式", path.display()))
+        .set_buttons(MessageButtons::YesNoCancelCustom(
+            "覆盖".to_string(),
+            "重命名".to_string(),
+            "跳过".to_string(),
+        ))
+        .show()
+        .await;
+    match result {
+        MessageDialogResult::Yes => ConflictPolicy::Overwrite,
+        MessageDialogResult::No => ConflictPolicy::Rename,
+        MessageDialogResult::Custom(label) if label == "覆盖" => ConflictPolicy::Overwrite,
+        MessageDialogResult::Custom(label) if label == "重命名" => ConflictPolicy::Rename,
+        _ => ConflictPolicy::Skip,
+    }
+}
+
+///
+/// 处理加密的压缩包, 缺少密码或密码错误时请求用户输入后重试, 用户跳过时返回原来的错误,
+/// 等待输入时任务被取消则返回[Cancelled]
+///
+/// # Arguments
+///
+/// * `archive`: 压缩包
+/// * `index`: 压缩包的序号
+/// * `cancel`: 取消标记
+/// * `ask`: 请求输入密码的通道
+/// * `run`: 处理压缩包
+///
+/// returns: Result<T, Error>
+///
+fn unlock<T>(
+    archive: &Archive,
+    index: usize,
+    cancel: &CancelToken,
+    ask: &UnboundedSender<(usize, PasswordError, oneshot::Sender<Option<Password>>)>,
+    mut run: impl FnMut(&Archive) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let mut archive = archive.clone();
+    loop {
+        let result = run(&archive);
+        let error = result
+            .as_ref()
+            .err()
+            .and_then(|e| e.downcast_ref::<PasswordError>())
+            .copied();
+        let Some(error) = error else {
+            return result;
+        };
+        let (answer_tx, answer_rx) = oneshot::channel();
+        let _ = ask.send((index, error, answer_tx));
+        match answer_rx.blocking_recv() {
+            Ok(Some(password)) => archive.passwords = vec![password],
+            // 取消任务时提示也以None结束, 不能当作用户跳过输入
+            _ if cancel.is_cancelled() => return Err(Cancelled.into()),
+            _ => return result,
+        }
+    }
+}
+
+fn send_status(tx: &UnboundedSender<Message>, index: usize, status: Status) {
+    tx.send(Message::UpdateArchiveStatus(index, status))
+        .expect("send archive status error")
+}
+
+fn send_log(tx: &UnboundedSender<Message>, entry: LogEntry) {
+    tx.send(Message::Log(entry)).expect("send log error")
+}
+
+///
+/// 选择保存的位置并导出日志, 取消选择时返回None
+///
+async fn export_log(content: String) -> anyhow::Result<Option<PathBuf>> {
+    let Some(handle) = AsyncFileDialog::default()
+        .set_title("导出日志")
+        .set_file_name("abz.log")
+        .save_file()
+        .await
+    else {
+        return Ok(None);
+    };
+    std::fs::write(handle.path(), content)?;
+    Ok(Some(handle.path().to_path_buf()))
+}
+
+async fn pick_save_folder(directory: Option<PathBuf>) -> Option<PathBuf> {
+    dialog(directory)
         .set_title("选择保存的文件夹")
         .set_can_create_directories(true)
         .pick_folder()
@@ -408,11 +1409,3 @@ fn container_style(theme: &Theme) -> container::Style {
     base.border.radius = Radius::default().top(5).bottom(5);
     base
 }
-
-fn mask_layer<'a, M: 'a>(element: impl Into<Element<'a, M>>) -> Element<'a, M> {
-    stack(vec![
-        element.into(),
-        opaque(Space::new(Length::Fill, Length::Fill)),
-    ])
-    .into()
-}
@@ -1,521 +1,1392 @@
-use crate::app::Message;
-use crate::format::Format;
+use crate::cancel::{CancelToken, Cancelled};
+use crate::codec;
+use crate::codec::{ArchiveReader, ArchiveWriter, Attribute, Entry, EntryKind};
+use crate::format::{Format, Level, NameEncoding, Target};
+use crate::options::{ConflictPolicy, Options, UnsupportedPolicy};
+use crate::password::Password;
 use crate::status::Status;
-use crate::widget::icon::icon;
-use anyhow::anyhow;
-use flate2::Compression;
-use human_bytes::human_bytes;
-use iced::widget::text::Wrapping;
-use iced::widget::{button, container, horizontal_space, hover, progress_bar, row, text};
-use iced::{Alignment, Border, Element, Length, Theme};
-use lucide_rs::Lucide;
-use std::fs::{File, metadata};
-use std::io::{BufReader, BufWriter, Read};
+use crate::verify;
+use crate::verify::{Checksum, Recorder};
+use anyhow::{anyhow, bail};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{Display, Formatter};
+use std::fs::{
+    DirEntry, File, create_dir_all, metadata, read_dir, remove_dir_all, remove_file, rename,
+    symlink_metadata,
+};
+use std::io::Read;
 use std::path::{Path, PathBuf};
-use tar::{EntryType, Header};
-use zip::write::FileOptions;
-use zip::{CompressionMethod, ZipArchive, ZipWriter};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 #[derive(Debug, Clone)]
 pub struct Archive {
     pub path: PathBuf,
     pub format: Format,
+    /// 后缀与实际内容不符时, 记录后缀对应的格式, 作为源打包的单独压缩的文件同样记录
+    pub mislabeled: Option<Format>,
     pub size: u64,
     pub status: Status,
+    /// 扫描目录时发现该文件的根目录, 单独选择的文件为空
+    pub root: Option<PathBuf>,
+    /// zip中文件名的编码, 默认自动识别
+    pub encoding: NameEncoding,
+    /// 加密的zip或7z依次尝试的密码
+    pub passwords: Vec<Password>,
+    /// 单独指定的目标格式, 为空时使用整批的目标格式
+    pub target: Option<Format>,
 }
 
 impl Archive {
+    ///
+    /// 解析文件, 优先根据文件头的魔数判断格式, 无法识别时再根据后缀判断
+    ///
+    /// 压缩格式的魔数之后还会解压开头的内容确认是tar, 单独压缩的文件(例如`access.log.gz`)
+    /// 即使后缀为`.tar.gz`也视为无法识别, 作为源打包时由[`Archive::unpacked`]记录后缀
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: 文件路径
+    ///
+    /// returns: Result<Option<Archive>, Error>
+    ///
     pub fn parse(path: impl AsRef<Path>) -> std::io::Result<Option<Self>> {
         let path = path.as_ref();
-        let metadata = metadata(&path)?;
-        match metadata.is_file() {
-            true => match path.file_name().and_then(|s| s.to_str()) {
-                Some(file_name) => match Format::parse(file_name) {
-                    Some(format) => Ok(Some(Archive {
-                        path: path.to_path_buf(),
-                        format,
-                        size: metadata.len(),
-                        status: Default::default(),
-                    })),
-                    None => Ok(None),
-                },
-                None => Ok(None),
-            },
-            false => Ok(None),
-        }
-    }
-
-    pub fn view(&self, index: usize) -> Element<'_, Message> {
-        let base = container(
-            row![
-                iced::widget::column![
-                    text(self.path.display().to_string()),
-                    row![
-                        text(human_bytes(self.size as f64))
-                            .width(Length::Fixed(60.))
-                            .wrapping(Wrapping::WordOrGlyph),
-                        text(self.status.to_string())
-                            .color(self.status.color())
-                            .width(Length::Fill)
-                    ]
-                    .align_y(Alignment::Center)
-                    .spacing(5),
-                ]
-                .spacing(5),
-                match self.status {
-                    Status::Processing(ratio) => Element::from(
-                        row![
-                            progress_bar(0.0..=1.0, ratio)
-                                .length(Length::Fixed(100.))
-                                .girth(Length::Fixed(5.)),
-                            text(format!("{:.02}%", ratio * 100.)).width(Length::Fixed(30.))
-                        ]
-                        .align_y(Alignment::Center)
-                        .spacing(5),
-                    ),
-                    Status::Success => Element::from(
-                        row![
-                            progress_bar(0.0..=1.0, 1.0)
-                                .length(Length::Fixed(100.))
-                                .girth(Length::Fixed(5.))
-                                .style(progress_bar::success),
-                            text("100%").width(Length::Fixed(30.)).style(text::success)
-                        ]
-                        .align_y(Alignment::Center)
-                        .spacing(5),
-                    ),
-                    _ => horizontal_space().width(Length::Fixed(130.)).into(),
-                },
-                horizontal_space().width(Length::Fixed(40.))
-            ]
-            .spacing(20)
-            .align_y(Alignment::Center),
-        )
-        .padding(5)
-        .width(Length::Fill)
-        .align_x(Alignment::Start)
-        .align_y(Alignment::Center)
-        .style(|theme: &Theme| {
-            container::transparent(theme).border(
-                Border::default()
-                    .width(1)
-                    .rounded(3)
-                    .color(theme.extended_palette().secondary.weak.color),
-            )
-        });
-        let top = container(button(icon(Lucide::Trash)).on_press(Message::RemoveArchive(index)))
-            .padding([0, 15])
-            .align_x(Alignment::End)
-            .align_y(Alignment::Center)
-            .width(Length::Fill)
-            .height(Length::Fill);
-        match self.status {
-            Status::Processing(_) => base.into(),
-            _ => hover(base, top).into(),
+        let metadata = metadata(path)?;
+        if !metadata.is_file() {
+            return Ok(None);
+        }
+        let (sniffed, labeled) = identify(path)?;
+        if is_compressed_file(path, sniffed) {
+            return Ok(None);
+        }
+        let (format, mislabeled) = match (sniffed, labeled) {
+            (Some(format), Some(labeled)) if format != labeled => (format, Some(labeled)),
+            (Some(format), _) => (format, None),
+            (None, Some(labeled)) => (labeled, None),
+            (None, None) => return Ok(None),
+        };
+        Ok(Some(Archive {
+            path: path.to_path_buf(),
+            format,
+            mislabeled,
+            size: metadata.len(),
+            status: Default::default(),
+            root: None,
+            encoding: Default::default(),
+            passwords: vec![],
+            target: None,
+        }))
+    }
+
+    ///
+    /// 将未打包的目录或文件作为源, 打包时目录中的内容位于压缩包的根目录,
+    /// 单个文件打包后压缩包中只有该文件
+    ///
+    /// 后缀为压缩包但实际是单独压缩的文件(例如`access.log.gz`改名为`access.tar.gz`)时,
+    /// 在`mislabeled`中记录后缀对应的格式, 以便调用方提示
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: 目录或文件路径
+    ///
+    /// returns: Result<Archive, Error>
+    ///
+    pub fn unpacked(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let mislabeled = match path.is_file() {
+            true => {
+                let (sniffed, labeled) = identify(path)?;
+                labeled.filter(|_| is_compressed_file(path, sniffed))
+            }
+            false => None,
+        };
+        Ok(Archive {
+            path: path.to_path_buf(),
+            format: Format::Directory,
+            mislabeled,
+            size: codec::reader(
+                Format::Directory,
+                path,
+                NameEncoding::Auto,
+                &[],
+                &CancelToken::default(),
+            )?
+            .size()?,
+            status: Default::default(),
+            root: None,
+            encoding: Default::default(),
+            passwords: vec![],
+            target: None,
+        })
+    }
+
+    ///
+    /// 后缀与实际内容不符时的提示
+    ///
+    pub fn mislabeled_warning(&self) -> Option<String> {
+        let labeled = self.mislabeled?;
+        Some(match self.format {
+            Format::Directory => format!(
+                "{}: 后缀为{}, 实际是单独压缩的文件, 将作为普通文件打包",
+                self.path.display(),
+                labeled
+            ),
+            format => format!(
+                "{}: 后缀为{}, 实际内容为{}",
+                self.path.display(),
+                labeled,
+                format
+            ),
+        })
+    }
+
+    ///
+    /// 记录扫描时的根目录, 用于在输出目录中还原目录结构
+    ///
+    pub fn with_root(self, root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: Some(root.into()),
+            ..self
+        }
+    }
+
+    ///
+    /// 该压缩包实际使用的目标格式及压缩参数
+    ///
+    /// 单独指定的格式与整批的格式不同时, 整批的压缩级别在该格式的范围内则沿用, 否则使用默认级别;
+    /// zip的压缩方法和7z的固实压缩只属于整批的格式, 该格式使用默认设置
+    ///
+    /// # Arguments
+    ///
+    /// * `target`: 整批的目标格式及压缩参数
+    ///
+    /// returns: Target
+    ///
+    pub fn resolve_target(&self, target: Target) -> Target {
+        match self.target {
+            Some(format) if format != target.format => {
+                let resolved = Target::new(format);
+                let level = match (target.level.value(), resolved.levels()) {
+                    (Some(level), Some(levels)) if levels.contains(&level) => target.level,
+                    _ => Level::Default,
+                };
+                Target { level, ..resolved }
+            }
+            _ => target,
         }
     }
 
+    ///
+    /// 所在目录相对于扫描根目录的路径, 没有根目录时为空路径
+    ///
+    pub fn relative_dir(&self) -> &Path {
+        self.root
+            .as_deref()
+            .zip(self.path.parent())
+            .and_then(|(root, parent)| parent.strip_prefix(root).ok())
+            .unwrap_or(Path::new(""))
+    }
+
+    ///
+    /// 将压缩包转换为目标格式并保存到指定目录
+    ///
+    /// 先写入目标目录下的隐藏临时文件, 成功后同步到磁盘再重命名为最终文件,
+    /// 失败或取消时删除临时文件, 保证最终文件名下不会出现不完整的压缩包
+    ///
+    /// 目标格式为[`Format::Directory`]时解压到`folder`下以压缩包文件名(不含后缀)命名的目录中
+    ///
+    /// 源格式与目标格式相同时按目标的压缩参数重新压缩, 开启`options.copy`时直接复制文件
+    ///
+    /// 开启`options.mirror`时, 输出到`folder`下与扫描根目录相同的相对路径中
+    ///
+    /// 输出文件已存在时按`options.conflict`处理, 跳过或询问时返回[`OutputExists`]错误
+    ///
+    /// # Arguments
+    ///
+    /// * `folder`: 保存的目录
+    /// * `target`: 目标格式及压缩参数
+    /// * `options`: 转换选项
+    /// * `cancel`: 取消标记, 取消后返回[`Cancelled`]错误
+    /// * `process`: 处理进度, 依次为[`Status::Processing`]和开启校验时的[`Status::Verifying`]
+    ///
+    /// returns: Result<Report, Error>
+    ///
     pub fn convert(
         &self,
         folder: PathBuf,
-        format: Format,
-        mut process: impl FnMut(f32),
-    ) -> anyhow::Result<()> {
+        target: Target,
+        options: &Options,
+        cancel: &CancelToken,
+        mut process: impl FnMut(Status),
+    ) -> anyhow::Result<Report> {
+        cancel.check()?;
         let file_name = self
             .path
             .file_name()
             .and_then(|s| s.to_str())
             .ok_or_else(|| anyhow!("获取文件file_name失败"))?;
-        let file_base = match self.format {
-            Format::Zip => file_name.strip_suffix(".zip").unwrap(),
-            Format::TarGz => file_name.strip_suffix(".tar.gz").unwrap(),
-            #[cfg(feature = "7z")]
-            Format::SevenZ => file_name.strip_suffix(".7z").unwrap(),
+        let file_base = self
+            .mislabeled
+            .unwrap_or(self.format)
+            .strip_suffix(file_name);
+        let format = target.format;
+        // 还原目录结构时, 子目录在确定要写入输出时才创建, 跳过或无法读取的压缩包不会留下空目录
+        let folder = match options.mirror {
+            true => folder.join(self.relative_dir()),
+            false => folder,
         };
-        match (self.format, format) {
-            (Format::Zip, Format::TarGz) => {
-                zip_to_tar_gz(
-                    &self.path,
-                    folder.join(format!("{}.tar.gz", file_base)),
-                    process,
-                )?;
+        // 后缀与内容不符时顺便纠正后缀
+        let extension = match (self.format == format, self.mislabeled) {
+            _ if format == Format::Directory => String::new(),
+            (true, None) => file_name[file_base.len()..].to_string(),
+            _ => format!(".{}", format.extension()),
+        };
+        let reservation = Reservation::new(&folder, file_base, &extension, options.conflict)?;
+        let output = reservation.path.clone();
+        let temp = temp_path(&output);
+        // 格式相同时默认按目标的压缩参数重新压缩, 选择了仅复制且不需要筛选条目或加密时才直接复制,
+        // 目录总是逐个条目处理
+        let copy = options.copy
+            && self.format == format
+            && format != Format::Directory
+            && options.filter.is_empty()
+            && options.password.is_none();
+        let result = match copy {
+            true => self.copy(&temp, options, cancel, &mut process),
+            false => self.transcode(&temp, target, options, cancel, &mut process),
+        }
+        .and_then(|report| {
+            // 文件会被直接替换, 目录需要先删除已存在的同名目录或文件
+            if format == Format::Directory {
+                match symlink_metadata(&output) {
+                    Ok(metadata) if metadata.is_dir() => remove_dir_all(&output)?,
+                    Ok(_) => remove_file(&output)?,
+                    Err(_) => {}
+                }
             }
-            (Format::TarGz, Format::Zip) => {
-                tar_gz_to_zip(
-                    &self.path,
-                    folder.join(format!("{}.zip", file_base)),
-                    process,
-                )?;
+            rename(&temp, &output)?;
+            Ok(Report { output, ..report })
+        });
+        if result.is_err() {
+            let _ = match format {
+                Format::Directory => remove_dir_all(&temp),
+                _ => remove_file(&temp),
+            };
+            cancel.check()?;
+        }
+        result
+    }
+
+    ///
+    /// 测试压缩包的完整性, 解压所有条目并校验压缩格式自带的校验和(zip的CRC32、gzip尾部、7z的CRC等),
+    /// 不写入任何文件
+    ///
+    /// # Arguments
+    ///
+    /// * `cancel`: 取消标记, 取消后返回[`Cancelled`]错误
+    /// * `process`: 处理进度, 为[`Status::Processing`]
+    ///
+    /// returns: Result<(), Error>
+    ///
+    pub fn test(
+        &self,
+        cancel: &CancelToken,
+        mut process: impl FnMut(Status),
+    ) -> anyhow::Result<()> {
+        cancel.check()?;
+        let result = codec::reader(
+            self.format,
+            &self.path,
+            self.encoding,
+            &self.passwords,
+            cancel,
+        )
+        .and_then(|mut reader| {
+            let total_size = reader.size()?;
+            let mut read_size = 0u64;
+            verify::scan(&mut *reader, cancel, &mut |size| {
+                read_size += size;
+                process(Status::Processing(ratio(read_size, total_size)))
+            })
+        });
+        if let Err(e) = result {
+            cancel.check()?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    ///
+    /// 选择了仅复制且格式相同时直接复制文件, 校验时比较整个文件的CRC32
+    ///
+    fn copy(
+        &self,
+        path: &Path,
+        options: &Options,
+        cancel: &CancelToken,
+        process: &mut dyn FnMut(Status),
+    ) -> anyhow::Result<Report> {
+        let source = File::open(&self.path)?;
+        let total_size = source.metadata()?.len();
+        create_parent(path)?;
+        let mut file = File::create_new(path)?;
+        let mut read_size = 0u64;
+        let mut source = Checksum::new(ProcessRead::new(source, cancel, |size| {
+            read_size += size;
+            process(Status::Processing(ratio(read_size, total_size)))
+        }));
+        std::io::copy(&mut source, &mut file)?;
+        file.sync_all()?;
+        if options.verify {
+            let expected = source.digest(&Entry::default());
+            let mut read_size = 0u64;
+            let mut target = Checksum::new(ProcessRead::new(File::open(path)?, cancel, |size| {
+                read_size += size;
+                process(Status::Verifying(ratio(read_size, total_size)))
+            }));
+            std::io::copy(&mut target, &mut std::io::sink())?;
+            let actual = target.digest(&Entry::default());
+            if expected != actual {
+                bail!(
+                    "校验失败: 复制后的文件与源文件不一致 (大小 {} -> {})",
+                    expected.size,
+                    actual.size
+                );
             }
-            #[cfg(feature = "7z")]
-            (Format::Zip, Format::SevenZ) => {
-                zip_to_seven_z(
-                    &self.path,
-                    folder.join(format!("{}.7z", file_base)),
-                    process,
-                )?;
+        }
+        Ok(Report::default())
+    }
+
+    ///
+    /// 逐个条目读取源压缩包并写入目标格式, 校验时重新读取写入的压缩包并比较每个条目
+    ///
+    fn transcode(
+        &self,
+        path: &Path,
+        target: Target,
+        options: &Options,
+        cancel: &CancelToken,
+        process: &mut dyn FnMut(Status),
+    ) -> anyhow::Result<Report> {
+        // 临时文件位于输出目录中, 打包目录时输出目录需要先存在, 位于源目录之中时才能被识别并跳过
+        if self.format == Format::Directory {
+            create_parent(path)?;
+        }
+        let skip = path.parent().and_then(|folder| self.nested_folder(folder));
+        let mut reader = self.reader(skip.as_deref(), cancel)?;
+        let total_size = reader.size()?;
+        let mut read_size = 0u64;
+        create_parent(path)?;
+        let mut writer = Recorder::new(codec::writer(&target, path, options.password.as_ref())?);
+        let mut report = Report::default();
+        // 所有链接的目标路径, 用于跟随链接链
+        let mut links = HashMap::new();
+        // 需要转为普通文件的链接
+        let mut pending = vec![];
+        reader.for_each(&mut |mut entry, data| {
+            if let Some(target) = link_target(&entry) {
+                links.insert(entry.path.clone(), target);
+            }
+            if !options.filter.matches(&entry.path) {
+                return Ok(());
             }
-            #[cfg(feature = "7z")]
-            (Format::SevenZ, Format::Zip) => {
-                seven_z_to_zip(
-                    &self.path,
-                    folder.join(format!("{}.zip", file_base)),
-                    process,
-                )?;
+            if !writer.kinds().contains(&entry.kind) {
+                let policy = match entry.kind {
+                    EntryKind::Hardlink => options.hardlink,
+                    _ => options.unsupported,
+                };
+                match policy {
+                    UnsupportedPolicy::Skip => {
+                        report.skipped.push(entry.path);
+                        return Ok(());
+                    }
+                    UnsupportedPolicy::Fail => {
+                        bail!("目标格式不支持{}: {}", entry.kind, entry.path)
+                    }
+                    UnsupportedPolicy::Materialize => match entry.kind {
+                        EntryKind::Symlink | EntryKind::Hardlink => {
+                            pending.push(entry);
+                            return Ok(());
+                        }
+                        _ => {
+                            entry.kind = EntryKind::File;
+                            entry.size = 0;
+                            entry.device = None;
+                            return writer.write(&entry, &mut std::io::empty());
+                        }
+                    },
+                }
             }
-            #[cfg(feature = "7z")]
-            (Format::SevenZ, Format::TarGz) => {
-                seven_z_to_tar_gz(
-                    &self.path,
-                    folder.join(format!("{}.tar.gz", file_base)),
-                    process,
-                )?;
+            report.dropped.extend(writer.dropped(&entry));
+            writer.write(
+                &entry,
+                &mut ProcessRead::new(data, cancel, |size| {
+                    read_size += size;
+                    process(Status::Processing(ratio(read_size, total_size)))
+                }),
+            )
+        })?;
+        if !pending.is_empty() {
+            cancel.check()?;
+            self.materialize(
+                &mut writer,
+                pending,
+                &links,
+                skip.as_deref(),
+                cancel,
+                &mut report,
+            )?;
+        }
+        let expected = writer.close()?;
+        if options.verify {
+            let total_size = expected.size();
+            let mut read_size = 0u64;
+            let actual = verify::scan(
+                &mut *codec::reader(
+                    target.format,
+                    path,
+                    NameEncoding::Utf8,
+                    options.password.as_slice(),
+                    cancel,
+                )?,
+                cancel,
+                &mut |size| {
+                    read_size += size;
+                    process(Status::Verifying(ratio(read_size, total_size)))
+                },
+            )?;
+            let differences = expected.diff(&actual);
+            if !differences.is_empty() {
+                bail!(
+                    "校验失败, 共{}处差异: {}",
+                    differences.len(),
+                    differences
+                        .iter()
+                        .take(5)
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                );
             }
-            #[cfg(feature = "7z")]
-            (Format::TarGz, Format::SevenZ) => {
-                tar_gz_to_seven_z(
-                    &self.path,
-                    folder.join(format!("{}.7z", file_base)),
-                    process,
-                )?;
+        }
+        Ok(report)
+    }
+
+    ///
+    /// 打开源压缩包, 源目录中包含输出目录时跳过输出目录
+    ///
+    fn reader(
+        &self,
+        skip: Option<&Path>,
+        cancel: &CancelToken,
+    ) -> anyhow::Result<Box<dyn ArchiveReader>> {
+        match skip {
+            Some(skip) => codec::directory_reader(&self.path, skip),
+            None => codec::reader(
+                self.format,
+                &self.path,
+                self.encoding,
+                &self.passwords,
+                cancel,
+            ),
+        }
+    }
+
+    ///
+    /// 打包目录时输出目录位于源目录之中(不含源目录本身)的情况下, 返回源目录中对应的路径,
+    /// 否则返回None
+    ///
+    /// 同一批次中先转换的输出也在输出目录中, 不跳过会被一起打包
+    ///
+    fn nested_folder(&self, folder: &Path) -> Option<PathBuf> {
+        if self.format != Format::Directory {
+            return None;
+        }
+        let source = self.path.canonicalize().ok()?;
+        let folder = folder.canonicalize().ok()?;
+        let relative = folder.strip_prefix(&source).ok()?;
+        (!relative.as_os_str().is_empty()).then(|| self.path.join(relative))
+    }
+
+    ///
+    /// 将链接转为其最终目标的普通文件, 目标不存在或不是普通文件时跳过
+    ///
+    /// # Arguments
+    ///
+    /// * `writer`: 目标压缩包
+    /// * `pending`: 需要转换的链接
+    /// * `links`: 压缩包中所有链接的目标路径
+    /// * `skip`: 源目录中需要跳过的输出目录
+    /// * `cancel`: 取消标记, 第二遍读取时同样检查
+    /// * `report`: 无法转换的链接记入报告
+    ///
+    /// returns: Result<(), Error>
+    ///
+    fn materialize(
+        &self,
+        writer: &mut dyn codec::ArchiveWriter,
+        pending: Vec<Entry>,
+        links: &HashMap<String, String>,
+        skip: Option<&Path>,
+        cancel: &CancelToken,
+        report: &mut Report,
+    ) -> anyhow::Result<()> {
+        let mut targets: HashMap<String, Vec<Entry>> = HashMap::new();
+        for entry in pending {
+            // 最多跟随40层, 与linux解析符号链接的限制一致
+            let mut target = links.get(&entry.path).cloned();
+            for _ in 0..40 {
+                match target.as_ref().and_then(|path| links.get(path)) {
+                    Some(next) => target = Some(next.clone()),
+                    None => break,
+                }
             }
-            _ => {
-                let metadata = self.path.metadata()?;
-                let total_size = metadata.len();
-                let mut read_size = 0u64;
-                let source = File::open(&self.path)?;
-                let mut target = File::create(folder.join(file_name))?;
-                std::io::copy(
-                    &mut ProcessRead::new(source, |size| {
-                        read_size += size;
-                        process((read_size as f64 / total_size as f64) as f32)
-                    }),
-                    &mut target,
-                )?;
+            match target {
+                Some(target) => targets.entry(target).or_default().push(entry),
+                None => report.skipped.push(entry.path),
             }
         }
+        // 第二遍读取源压缩包, 取出链接目标的内容
+        let mut reader = self.reader(skip, cancel)?;
+        reader.for_each(&mut |target, data| {
+            cancel.check()?;
+            if target.kind != EntryKind::File {
+                return Ok(());
+            }
+            let Some(entries) = targets.remove(&target.path) else {
+                return Ok(());
+            };
+            let mut content = vec![];
+            ProcessRead::new(data, cancel, |_| {}).read_to_end(&mut content)?;
+            for mut entry in entries {
+                entry.kind = EntryKind::File;
+                entry.size = content.len() as u64;
+                entry.crc32 = target.crc32;
+                entry.link = None;
+                entry.mode = target.mode;
+                writer.write(&entry, &mut content.as_slice())?;
+            }
+            Ok(())
+        })?;
+        report
+            .skipped
+            .extend(targets.into_values().flatten().map(|entry| entry.path));
         Ok(())
     }
 }
 
-macro_rules! io {
-    ($source:expr, $target:expr) => {
-        (
-            BufReader::new(File::open($source)?),
-            BufWriter::new(File::create($target)?),
-        )
-    };
-}
-
-///
-/// zip压缩包转为tar.gz
-///
-/// # Arguments
 ///
-/// * `source`: 源文件
-/// * `target`: 目标文件
-/// * `process`: 处理进度
+/// 输出文件已存在且冲突策略为跳过或询问时返回的错误
 ///
-/// returns: Result<(), Error>
-///
-fn zip_to_tar_gz(
-    source: impl AsRef<Path>,
-    target: impl AsRef<Path>,
-    mut process: impl FnMut(f32),
-) -> anyhow::Result<()> {
-    let (source, target) = io!(source, target);
-    let mut zip = ZipArchive::new(BufReader::new(source))?;
-    let gz = flate2::write::GzEncoder::new(target, Compression::default());
-    let mut tar_gz = tar::Builder::new(gz);
-    let total_size = zip.decompressed_size().unwrap_or(u128::MAX);
-    let mut read_size = 0u64;
-    for index in 0..zip.len() {
-        let file = zip.by_index(index)?;
-        let path = file.name().to_string();
-        let mut header = Header::new_gnu();
-        header.set_size(file.size());
-        header.set_mode(file.unix_mode().unwrap_or(0o644));
-        header.set_entry_type(if file.is_dir() {
-            EntryType::Directory
-        } else {
-            EntryType::Regular
-        });
-        header.set_cksum();
-        tar_gz.append_data(
-            &mut header,
-            path,
-            ProcessRead::new(file, |size| {
-                read_size += size;
-                process((read_size as f64 / total_size as f64) as f32);
-            }),
-        )?;
+#[derive(Debug, Clone)]
+pub struct OutputExists(pub PathBuf);
+
+impl Display for OutputExists {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "目标文件已存在: {}", self.0.display())
     }
-    tar_gz.finish()?;
-    Ok(())
 }
 
+impl std::error::Error for OutputExists {}
+
+/// 正在转换中的输出文件, 同一批次中同名的输出也视为冲突
+static RESERVED: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
 ///
-/// tar.gz压缩包转为zip压缩包
+/// 预留的输出路径, 释放时从预留列表中移除
 ///
-/// # Arguments
+struct Reservation {
+    path: PathBuf,
+}
+
+impl Reservation {
+    ///
+    /// 按冲突策略确定输出路径并预留
+    ///
+    /// # Arguments
+    ///
+    /// * `folder`: 保存的目录
+    /// * `base`: 不含后缀的文件名
+    /// * `extension`: 带`.`的后缀, 可以为空
+    /// * `policy`: 冲突策略
+    ///
+    /// returns: Result<Reservation, Error>
+    ///
+    fn new(
+        folder: &Path,
+        base: &str,
+        extension: &str,
+        policy: ConflictPolicy,
+    ) -> anyhow::Result<Self> {
+        let mut reserved = RESERVED.lock().expect("lock reserved outputs error");
+        let exists = |path: &Path| path.exists() || reserved.contains(path);
+        let mut path = folder.join(format!("{}{}", base, extension));
+        if exists(&path) {
+            match policy {
+                ConflictPolicy::Overwrite => {}
+                ConflictPolicy::Skip | ConflictPolicy::Ask => bail!(OutputExists(path)),
+                ConflictPolicy::Rename => {
+                    path = (1..)
+                        .map(|index| folder.join(format!("{} ({}){}", base, index, extension)))
+                        .find(|path| !exists(path))
+                        .expect("no available output name");
+                }
+            }
+        }
+        reserved.insert(path.clone());
+        Ok(Self { path })
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if let Ok(mut reserved) = RESERVED.lock() {
+            reserved.remove(&self.path);
+        }
+    }
+}
+
 ///
-/// * `source`: 源文件
-/// * `target`: 目标文件
-/// * `process`: 处理进度
+/// 输出文件对应的隐藏临时文件, 与输出文件位于同一目录以保证重命名是原子的
 ///
-/// returns: Result<(), Error>
+/// 文件名中带上进程号和序号, 避免同时转换的同名输出互相覆盖
 ///
-fn tar_gz_to_zip(
-    source: impl AsRef<Path>,
-    target: impl AsRef<Path>,
-    mut process: impl FnMut(f32),
-) -> anyhow::Result<()> {
-    let total_size = get_tar_gz_decompress_size(source.as_ref())?;
-    let mut read_size = 0u64;
-    let (source, target) = io!(source, target);
-    let gz = flate2::read::GzDecoder::new(source);
-    let mut tar_gz = tar::Archive::new(gz);
-    let mut zip = ZipWriter::new(target);
-    for entry in tar_gz.entries()? {
-        let mut entry = entry?;
-        let header = entry.header();
-        let path = header.path()?;
-        let unix_mode = header.mode()?;
-        let options = FileOptions::<()>::default()
-            .compression_method(CompressionMethod::Bzip2)
-            .unix_permissions(unix_mode);
-        if header.entry_type().is_dir() {
-            zip.add_directory_from_path(path, options)?
-        } else {
-            zip.start_file_from_path(path, options)?;
-            read_size += std::io::copy(&mut entry, &mut zip)?;
-            process((read_size as f64 / total_size as f64) as f32)
-        }
-    }
-    zip.finish().map(|_| ())?;
-    Ok(())
+fn temp_path(output: &Path) -> PathBuf {
+    static SEQUENCE: AtomicUsize = AtomicUsize::new(0);
+    let file_name = output
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    output.with_file_name(format!(
+        ".{}.{}-{}.{}",
+        file_name,
+        std::process::id(),
+        SEQUENCE.fetch_add(1, Ordering::Relaxed),
+        codec::TEMP_EXTENSION
+    ))
 }
+
 ///
-/// tar.gz压缩包转为7z压缩包
+/// 根据文件头的魔数和文件名后缀识别格式
 ///
-/// # Arguments
+/// returns: Result<(魔数识别的格式, 后缀对应的格式), Error>
 ///
-/// * `source`: 源文件
-/// * `target`: 目标文件
-/// * `process`: 处理进度
+fn identify(path: &Path) -> std::io::Result<(Option<Format>, Option<Format>)> {
+    let mut header = Vec::with_capacity(8);
+    File::open(path)?.take(8).read_to_end(&mut header)?;
+    let labeled = path
+        .file_name()
+        .and_then(|s| Format::parse(&s.to_string_lossy()));
+    Ok((Format::sniff(&header), labeled))
+}
+
 ///
-/// returns: Result<(), Error>
+/// 魔数为压缩格式, 但解压开头的内容后不是tar, 即单独压缩的文件
 ///
-#[cfg(feature = "7z")]
-fn tar_gz_to_seven_z(
-    source: impl AsRef<Path>,
-    target: impl AsRef<Path>,
-    mut process: impl FnMut(f32),
-) -> anyhow::Result<()> {
-    let total_size = get_tar_gz_decompress_size(source.as_ref())?;
-    let mut read_size = 0u64;
-    let (source, target) = io!(source, target);
-    let gz = flate2::read::GzDecoder::new(source);
-    let mut tar_gz = tar::Archive::new(gz);
-    let mut seven_z = sevenz_rust2::ArchiveWriter::new(target)?;
-    for entry in tar_gz.entries()? {
-        let file = entry?;
-        let path = file.path().map(|s| s.display().to_string())?;
-        if file.header().entry_type().is_dir() {
-            let entry = sevenz_rust2::ArchiveEntry::new_directory(&path);
-            seven_z.push_archive_entry::<File>(entry, None)?;
-        } else {
-            let entry = sevenz_rust2::ArchiveEntry::new_file(&path);
-            seven_z.push_archive_entry(
-                entry,
-                Some(ProcessRead::new(file, |size| {
-                    read_size += size;
-                    process((size as f64 / total_size as f64) as f32)
-                })),
-            )?;
-        }
-    }
-    seven_z.finish()?;
-    Ok(())
+fn is_compressed_file(path: &Path, sniffed: Option<Format>) -> bool {
+    // 压缩流已损坏时无法确认, 仍然按魔数识别, 留到转换或测试时报告具体的错误
+    sniffed.is_some_and(|format| !codec::is_tarball(format, path).unwrap_or(true))
 }
 
 ///
-/// 7z压缩包转为tar.gz压缩包
+/// 创建输出文件所在的目录, 还原目录结构时该目录可能还不存在
 ///
-/// # Arguments
+fn create_parent(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(parent) => create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
 ///
-/// * `source`: 源文件
-/// * `target`: 目标文件
-/// * `process`: 处理进度
+/// 计算链接指向的条目在压缩包中的路径, 符号链接相对于所在目录解析
 ///
-/// returns: Result<(), Error>
+/// 绝对路径或超出压缩包根目录的链接无法解析, 返回None
 ///
-#[cfg(feature = "7z")]
-fn seven_z_to_tar_gz(
-    source: impl AsRef<Path>,
-    target: impl AsRef<Path>,
-    mut process: impl FnMut(f32),
-) -> anyhow::Result<()> {
-    let (source, target) = io!(source, target);
-    let mut seven_z = sevenz_rust2::ArchiveReader::new(source, sevenz_rust2::Password::empty())?;
-    let total_size = seven_z
-        .archive()
-        .files
-        .iter()
-        .map(|entry| entry.size)
-        .sum::<u64>();
-    let mut read_size = 0u64;
-    let gz = flate2::write::GzEncoder::new(target, Compression::default());
-    let mut tar_gz = tar::Builder::new(gz);
-    let extract = |entry: &sevenz_rust2::ArchiveEntry, reader: &mut dyn Read| {
-        let mut header = Header::new_gnu();
-        header.set_size(entry.size());
-        header.set_mode(0o644);
-        header.set_entry_type(if entry.is_directory {
-            EntryType::Directory
-        } else {
-            EntryType::Regular
-        });
-        header.set_cksum();
-        tar_gz.append_data(
-            &mut header,
-            &entry.name,
-            ProcessRead::new(reader, |size| {
-                read_size += size;
-                process((read_size as f64 / total_size as f64) as f32);
-            }),
-        )?;
-        Ok(true)
+fn link_target(entry: &Entry) -> Option<String> {
+    let link = entry.link.as_deref()?;
+    let base = match entry.kind {
+        EntryKind::Hardlink => "",
+        EntryKind::Symlink if !link.starts_with('/') => entry
+            .path
+            .rsplit_once('/')
+            .map(|(parent, _)| parent)
+            .unwrap_or(""),
+        _ => return None,
     };
-    seven_z.for_each_entries(extract)?;
-    tar_gz.finish()?;
-    Ok(())
+    let mut parts = vec![];
+    for part in base.split('/').chain(link.split('/')) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
 }
 
 ///
-/// 7z压缩包转为zip压缩包
-///
-/// # Arguments
-///
-/// * `source`: 源文件
-/// * `target`: 目标文件
-/// * `process`: 处理进度
-///
-/// returns: Result<(), Error>
+/// 转换结果的报告
 ///
-#[cfg(feature = "7z")]
-fn seven_z_to_zip(
-    source: impl AsRef<Path>,
-    target: impl AsRef<Path>,
-    mut process: impl FnMut(f32),
-) -> anyhow::Result<()> {
-    let (source, target) = io!(source, target);
-    let mut seven_z = sevenz_rust2::ArchiveReader::new(source, sevenz_rust2::Password::empty())?;
-    let total_size = seven_z
-        .archive()
-        .files
-        .iter()
-        .map(|entry| entry.size)
-        .sum::<u64>();
-    let mut read_size = 0u64;
-    let mut zip = ZipWriter::new(target);
-    let extract = |entry: &sevenz_rust2::ArchiveEntry, reader: &mut dyn Read| {
-        fn map_to_seven_z_error(source: ZipError) -> sevenz_rust2::Error {
-            match source {
-                ZipError::Io(e) => e.into(),
-                ZipError::FileNotFound => sevenz_rust2::Error::FileNotFound,
-                _ => sevenz_rust2::Error::Other(source.to_string().into()),
-            }
-        }
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// 实际写入的文件, 自动重命名时与默认的文件名不同
+    pub output: PathBuf,
+    /// 目标格式无法保留的属性
+    pub dropped: BTreeSet<Attribute>,
+    /// 目标格式无法保存而被跳过的条目
+    pub skipped: Vec<String>,
+}
+
+impl Report {
+    pub fn is_empty(&self) -> bool {
+        self.dropped.is_empty() && self.skipped.is_empty()
+    }
+}
 
-        let options = FileOptions::<()>::default()
-            .compression_method(CompressionMethod::Bzip2)
-            .unix_permissions(0o644);
-        if entry.is_directory {
-            zip.add_directory_from_path(entry.name(), options)
-                .map_err(map_to_seven_z_error)?;
-        } else {
-            zip.start_file_from_path(entry.name(), options)
-                .map_err(map_to_seven_z_error)?;
-            read_size += std::io::copy(reader, &mut zip)?;
-            process((read_size as f64 / total_size as f64) as f32)
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut parts = vec![];
+        if !self.dropped.is_empty() {
+            let dropped = self
+                .dropped
+                .iter()
+                .map(Attribute::to_string)
+                .collect::<Vec<_>>();
+            parts.push(format!("目标格式不支持保留{}", dropped.join("、")));
         }
-        Ok(true)
-    };
-    seven_z.for_each_entries(extract)?;
-    zip.finish()?;
-    Ok(())
+        if !self.skipped.is_empty() {
+            parts.push(format!(
+                "跳过{}个条目: {}",
+                self.skipped.len(),
+                self.skipped.join("、")
+            ));
+        }
+        write!(f, "{}", parts.join("; "))
+    }
 }
 
 ///
-/// zip压缩包转为7z压缩包
+/// 列出目录下的所有文件
 ///
 /// # Arguments
 ///
-/// * `source`: 源文件
-/// * `target`: 目标文件
-/// * `process`: 处理进度
+/// * `folder`: 目录
+/// * `recursion`: 是否递归子目录
 ///
-/// returns: Result<(), Error>
+/// returns: Result<Vec<DirEntry>, Error>
 ///
-#[cfg(feature = "7z")]
-fn zip_to_seven_z(
-    source: impl AsRef<Path>,
-    target: impl AsRef<Path>,
-    mut process: impl FnMut(f32),
-) -> anyhow::Result<()> {
-    let (source, target) = io!(source, target);
-    let mut zip = ZipArchive::new(source)?;
-    let total_size = zip.decompressed_size().unwrap_or(u128::MAX);
-    let mut read_size = 0u64;
-    let mut seven_z = sevenz_rust2::ArchiveWriter::new(target)?;
-    for index in 0..zip.len() {
-        let file = zip.by_index(index)?;
-        if file.is_dir() {
-            let entry = sevenz_rust2::ArchiveEntry::new_directory(file.name());
-            seven_z.push_archive_entry::<File>(entry, None)?;
-        } else {
-            let entry = sevenz_rust2::ArchiveEntry::new_file(file.name());
-            seven_z.push_archive_entry(
-                entry,
-                Some(ProcessRead::new(file, |size| {
-                    read_size += size;
-                    process((read_size as f64 / total_size as f64) as f32)
-                })),
-            )?;
-        }
+pub fn list_archive_entry(folder: &Path, recursion: bool) -> anyhow::Result<Vec<DirEntry>> {
+    let entries = read_dir(folder).map_err(|e| anyhow!(e))?;
+    match recursion {
+        true => entries
+            .into_iter()
+            .try_fold(vec![], |mut dirs, entry| match entry {
+                Ok(entry) => match entry.file_type() {
+                    Ok(file_type) => {
+                        if file_type.is_dir() {
+                            match list_archive_entry(&entry.path(), recursion) {
+                                Ok(sub_dirs) => {
+                                    dirs.extend(sub_dirs);
+                                    Ok(dirs)
+                                }
+                                Err(e) => Err(anyhow!(e)),
+                            }
+                        } else {
+                            dirs.push(entry);
+                            Ok(dirs)
+                        }
+                    }
+                    Err(e) => Err(anyhow!(e)),
+                },
+                Err(e) => Err(anyhow!(e)),
+            }),
+        false => entries
+            .into_iter()
+            .try_fold(vec![], |mut entries, entry| match entry {
+                Ok(entry) => {
+                    entries.push(entry);
+                    Ok(entries)
+                }
+                Err(e) => Err(anyhow!(e)),
+            }),
     }
-    seven_z.finish()?;
-    Ok(())
 }
 
-fn get_tar_gz_decompress_size(source: impl AsRef<Path>) -> anyhow::Result<u64> {
-    let gz = flate2::read::GzDecoder::new(BufReader::new(File::open(source)?));
-    let mut tar_gz = tar::Archive::new(gz);
-    let entries = tar_gz.entries()?;
-    Ok(entries
-        .into_iter()
-        .flatten()
-        .filter(|entry| entry.header().entry_type().is_file())
-        .flat_map(|entry| entry.header().size())
-        .sum())
+///
+/// 计算进度比例, 总大小未知时返回0, 实际读取的数据多于预计时不超过1
+///
+fn ratio(current: u64, total: u64) -> f32 {
+    match total {
+        0 => 0.,
+        _ => (current as f64 / total as f64).min(1.) as f32,
+    }
 }
 
-struct ProcessRead<R, F> {
+///
+/// 统计读取进度的读取器, 每次读取前检查是否已取消
+///
+pub(crate) struct ProcessRead<'a, R, F> {
     read: R,
+    cancel: &'a CancelToken,
     f: F,
 }
 
-impl<R, F> ProcessRead<R, F> {
-    pub fn new(read: R, f: F) -> Self {
-        Self { read, f }
+impl<'a, R, F> ProcessRead<'a, R, F> {
+    pub fn new(read: R, cancel: &'a CancelToken, f: F) -> Self {
+        Self { read, cancel, f }
     }
 }
 
-impl<R: Read, F: FnMut(u64)> Read for ProcessRead<R, F> {
+impl<R: Read, F: FnMut(u64)> Read for ProcessRead<'_, R, F> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.cancel.is_cancelled() {
+            return Err(std::io::Error::other(Cancelled));
+        }
         let size = self.read.read(buf)?;
         (self.f)(size as u64);
         Ok(size)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::ZipMethod;
+    use crate::testing::TempDir;
+
+    fn link(kind: EntryKind, path: &str, link: &str) -> Entry {
+        Entry {
+            path: path.to_string(),
+            kind,
+            link: Some(link.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn hardlink_target_is_archive_path() {
+        let entry = link(EntryKind::Hardlink, "a/b", "x/y");
+        assert_eq!(link_target(&entry).as_deref(), Some("x/y"));
+    }
+
+    #[test]
+    fn symlink_target_is_relative_to_parent() {
+        let entry = link(EntryKind::Symlink, "dir/link", "../file");
+        assert_eq!(link_target(&entry).as_deref(), Some("file"));
+        let entry = link(EntryKind::Symlink, "dir/link", "sub/./file");
+        assert_eq!(link_target(&entry).as_deref(), Some("dir/sub/file"));
+        let entry = link(EntryKind::Symlink, "link", "file");
+        assert_eq!(link_target(&entry).as_deref(), Some("file"));
+    }
+
+    #[test]
+    fn unresolvable_link_target() {
+        // 绝对路径和超出根目录的链接无法在压缩包中解析
+        assert_eq!(
+            link_target(&link(EntryKind::Symlink, "a/link", "/etc/passwd")),
+            None
+        );
+        assert_eq!(
+            link_target(&link(EntryKind::Symlink, "a/link", "../../x")),
+            None
+        );
+        assert_eq!(
+            link_target(&link(EntryKind::Hardlink, "link", "../x")),
+            None
+        );
+        assert_eq!(link_target(&link(EntryKind::File, "file", "x")), None);
+        assert_eq!(link_target(&Entry::default()), None);
+    }
+
+    #[test]
+    fn resolve_target_keeps_applicable_level() {
+        let temp = TempDir::new();
+        let mut archive = Archive::unpacked(temp.path()).unwrap();
+        let batch = Target {
+            method: ZipMethod::Zstd,
+            level: Level::Custom(15),
+            ..Target::new(Format::Zip)
+        };
+        assert_eq!(archive.resolve_target(batch), batch);
+        archive.target = Some(Format::Zip);
+        assert_eq!(archive.resolve_target(batch), batch);
+        // 超出tar.gz的级别范围, 使用默认级别
+        archive.target = Some(Format::TarGz);
+        assert_eq!(archive.resolve_target(batch), Target::new(Format::TarGz));
+        let batch = Target {
+            level: Level::Custom(3),
+            ..batch
+        };
+        assert_eq!(
+            archive.resolve_target(batch),
+            Target {
+                level: Level::Custom(3),
+                ..Target::new(Format::TarGz)
+            }
+        );
+        // 目录不支持设置级别
+        archive.target = Some(Format::Directory);
+        assert_eq!(
+            archive.resolve_target(batch),
+            Target::new(Format::Directory)
+        );
+    }
+
+    #[test]
+    fn reservation_without_conflict() {
+        let temp = TempDir::new();
+        for policy in ConflictPolicy::ALL {
+            let reservation = Reservation::new(temp.path(), "data", ".zip", policy).unwrap();
+            assert_eq!(reservation.path, temp.path().join("data.zip"));
+        }
+    }
+
+    #[test]
+    fn reservation_rename_numbering() {
+        let temp = TempDir::new();
+        std::fs::write(temp.path().join("data.tar.gz"), "").unwrap();
+        let rename = || Reservation::new(temp.path(), "data", ".tar.gz", ConflictPolicy::Rename);
+        let first = rename().unwrap();
+        assert_eq!(first.path, temp.path().join("data (1).tar.gz"));
+        // 同一批次中已预留的名称同样视为冲突
+        let second = rename().unwrap();
+        assert_eq!(second.path, temp.path().join("data (2).tar.gz"));
+        // 释放后可以再次使用
+        drop(first);
+        assert_eq!(rename().unwrap().path, temp.path().join("data (1).tar.gz"));
+        drop(second);
+    }
+
+    #[test]
+    fn reservation_rename_without_extension() {
+        let temp = TempDir::new();
+        std::fs::create_dir(temp.path().join("data")).unwrap();
+        let reservation =
+            Reservation::new(temp.path(), "data", "", ConflictPolicy::Rename).unwrap();
+        assert_eq!(reservation.path, temp.path().join("data (1)"));
+    }
+
+    #[test]
+    fn reservation_skip_and_overwrite() {
+        let temp = TempDir::new();
+        let output = temp.path().join("data.zip");
+        std::fs::write(&output, "").unwrap();
+        for policy in [ConflictPolicy::Skip, ConflictPolicy::Ask] {
+            let error = Reservation::new(temp.path(), "data", ".zip", policy)
+                .err()
+                .unwrap();
+            assert_eq!(error.downcast_ref::<OutputExists>().unwrap().0, output);
+        }
+        let reservation =
+            Reservation::new(temp.path(), "data", ".zip", ConflictPolicy::Overwrite).unwrap();
+        assert_eq!(reservation.path, output);
+    }
+
+    fn entry(kind: EntryKind, path: &str, content: &str, link: Option<&str>) -> (Entry, String) {
+        let entry = Entry {
+            path: path.to_string(),
+            kind,
+            size: content.len() as u64,
+            mode: Some(0o640),
+            link: link.map(str::to_string),
+            ..Default::default()
+        };
+        (entry, content.to_string())
+    }
+
+    fn read_entries(format: Format, source: &Path) -> Vec<(String, EntryKind, String)> {
+        let mut entries = vec![];
+        codec::reader(
+            format,
+            source,
+            NameEncoding::Auto,
+            &[],
+            &CancelToken::default(),
+        )
+        .unwrap()
+        .for_each(&mut |entry, data| {
+            let mut content = String::new();
+            data.read_to_string(&mut content)?;
+            entries.push((entry.path, entry.kind, content));
+            Ok(())
+        })
+        .unwrap();
+        entries
+    }
+
+    #[test]
+    fn hardlink_round_trip_through_zip() {
+        let temp = TempDir::new();
+        let source = temp.path().join("data.tar.gz");
+        let mut writer = codec::writer(&Target::new(Format::TarGz), &source, None).unwrap();
+        for (entry, content) in [
+            entry(EntryKind::File, "a.txt", "hello", None),
+            entry(EntryKind::Hardlink, "hard", "", Some("a.txt")),
+            entry(EntryKind::Symlink, "link", "", Some("a.txt")),
+        ] {
+            writer.write(&entry, &mut content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        // zip不能保存硬链接, 默认复制一份目标的内容
+        let convert = |source: &Path, format: Format, folder: &str| {
+            create_dir_all(temp.path().join(folder)).unwrap();
+            Archive::parse(source)
+                .unwrap()
+                .unwrap()
+                .convert(
+                    temp.path().join(folder),
+                    Target::new(format),
+                    &Options::default(),
+                    &CancelToken::default(),
+                    |_| {},
+                )
+                .unwrap()
+        };
+        let zip = convert(&source, Format::Zip, "zip");
+        assert!(zip.is_empty());
+        // 复制的硬链接在其它条目之后写入
+        let expected = vec![
+            ("a.txt".to_string(), EntryKind::File, "hello".to_string()),
+            ("link".to_string(), EntryKind::Symlink, String::new()),
+            ("hard".to_string(), EntryKind::File, "hello".to_string()),
+        ];
+        assert_eq!(read_entries(Format::Zip, &zip.output), expected);
+        let back = convert(&zip.output, Format::TarGz, "back");
+        assert_eq!(read_entries(Format::TarGz, &back.output), expected);
+    }
+
+    #[test]
+    fn convert_with_verify() {
+        let temp = TempDir::new();
+        let source = temp.path().join("data.zip");
+        let mut writer = codec::writer(&Target::new(Format::Zip), &source, None).unwrap();
+        for (entry, content) in [
+            entry(EntryKind::Directory, "dir", "", None),
+            entry(EntryKind::File, "dir/a.txt", "hello", None),
+            entry(EntryKind::File, "empty", "", None),
+            entry(EntryKind::Symlink, "link", "", Some("dir/a.txt")),
+        ] {
+            writer.write(&entry, &mut content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+        let archive = Archive::parse(&source).unwrap().unwrap();
+        let options = Options {
+            verify: true,
+            ..Default::default()
+        };
+        for format in [Format::TarGz, Format::Zip, Format::Directory] {
+            let folder = temp.path().join(format!("out-{:?}", format));
+            create_dir_all(&folder).unwrap();
+            archive
+                .convert(
+                    folder,
+                    Target::new(format),
+                    &options,
+                    &CancelToken::default(),
+                    |_| {},
+                )
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn extract_hardlink_sorted_before_target_with_verify() {
+        let temp = TempDir::new();
+        let source = temp.path().join("data.tar.gz");
+        let mut writer = codec::writer(&Target::new(Format::TarGz), &source, None).unwrap();
+        // 重新读取解压目录时按文件名排序, `a.txt`成为普通文件而`z.txt`成为硬链接
+        for (entry, content) in [
+            entry(EntryKind::File, "z.txt", "hello", None),
+            entry(EntryKind::Hardlink, "a.txt", "", Some("z.txt")),
+        ] {
+            writer.write(&entry, &mut content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+        let folder = temp.path().join("out");
+        create_dir_all(&folder).unwrap();
+        let options = Options {
+            verify: true,
+            ..Default::default()
+        };
+        let report = Archive::parse(&source)
+            .unwrap()
+            .unwrap()
+            .convert(
+                folder,
+                Target::new(Format::Directory),
+                &options,
+                &CancelToken::default(),
+                |_| {},
+            )
+            .unwrap();
+        for name in ["a.txt", "z.txt"] {
+            assert_eq!(
+                std::fs::read_to_string(report.output.join(name)).unwrap(),
+                "hello"
+            );
+        }
+    }
+
+    #[test]
+    fn extract_three_member_hardlink_group_with_verify() {
+        let temp = TempDir::new();
+        let source = temp.path().join("data.tar.gz");
+        let mut writer = codec::writer(&Target::new(Format::TarGz), &source, None).unwrap();
+        // 重新读取解压目录时`a.txt`成为普通文件, `m.txt`和`z.txt`都成为指向`a.txt`的硬链接
+        for (entry, content) in [
+            entry(EntryKind::File, "z.txt", "hello", None),
+            entry(EntryKind::Hardlink, "a.txt", "", Some("z.txt")),
+            entry(EntryKind::Hardlink, "m.txt", "", Some("z.txt")),
+        ] {
+            writer.write(&entry, &mut content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+        let folder = temp.path().join("out");
+        create_dir_all(&folder).unwrap();
+        let options = Options {
+            verify: true,
+            ..Default::default()
+        };
+        let report = Archive::parse(&source)
+            .unwrap()
+            .unwrap()
+            .convert(
+                folder,
+                Target::new(Format::Directory),
+                &options,
+                &CancelToken::default(),
+                |_| {},
+            )
+            .unwrap();
+        for name in ["a.txt", "m.txt", "z.txt"] {
+            assert_eq!(
+                std::fs::read_to_string(report.output.join(name)).unwrap(),
+                "hello"
+            );
+        }
+    }
+
+    #[test]
+    fn compressed_file_labeled_as_tarball() {
+        let temp = TempDir::new();
+        let source = temp.path().join("access.tar.gz");
+        let mut encoder = flate2::write::GzEncoder::new(
+            File::create_new(&source).unwrap(),
+            flate2::Compression::default(),
+        );
+        std::io::Write::write_all(&mut encoder, b"GET / HTTP/1.1").unwrap();
+        encoder.finish().unwrap();
+        assert!(Archive::parse(&source).unwrap().is_none());
+        let archive = Archive::unpacked(&source).unwrap();
+        assert_eq!(archive.format, Format::Directory);
+        assert_eq!(archive.mislabeled, Some(Format::TarGz));
+        assert!(archive.mislabeled_warning().unwrap().contains("单独压缩"));
+
+        // 真正的tar.gz作为源打包时不需要提示
+        let tarball = temp.path().join("data.tar.gz");
+        codec::writer(&Target::new(Format::TarGz), &tarball, None)
+            .unwrap()
+            .finish()
+            .unwrap();
+        assert_eq!(Archive::unpacked(&tarball).unwrap().mislabeled, None);
+    }
+
+    #[test]
+    fn cancel_during_transcode_removes_temp() {
+        let temp = TempDir::new();
+        let source = temp.path().join("data.tar.gz");
+        let mut writer = codec::writer(&Target::new(Format::TarGz), &source, None).unwrap();
+        let content = "x".repeat(64 * 1024);
+        for index in 0..4 {
+            let (entry, content) =
+                entry(EntryKind::File, &format!("{}.txt", index), &content, None);
+            writer.write(&entry, &mut content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+        let archive = Archive::parse(&source).unwrap().unwrap();
+        for format in [Format::Zip, Format::TarGz, Format::Directory] {
+            let folder = temp.path().join(format!("out-{:?}", format));
+            create_dir_all(&folder).unwrap();
+            let cancel = CancelToken::default();
+            // 读到第一块数据后取消, 此时临时文件已经写入了一部分
+            let error = archive
+                .convert(
+                    folder.clone(),
+                    Target::new(format),
+                    &Options::default(),
+                    &cancel,
+                    |_| cancel.cancel(),
+                )
+                .unwrap_err();
+            assert!(error.is::<Cancelled>(), "{:#}", error);
+            assert_eq!(read_dir(&folder).unwrap().count(), 0);
+        }
+    }
+
+    #[test]
+    fn failed_convert_leaves_folder_unchanged() {
+        let temp = TempDir::new();
+        let source = temp.path().join("data.tar.gz");
+        let mut writer = codec::writer(&Target::new(Format::TarGz), &source, None).unwrap();
+        // 伪随机内容无法压缩, 截断压缩包后条目内容不完整
+        let mut seed = 1u32;
+        let content = (0..64 * 1024)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect::<Vec<_>>();
+        let (entry, _) = entry(EntryKind::File, "random.bin", "", None);
+        let entry = Entry {
+            size: content.len() as u64,
+            ..entry
+        };
+        writer.write(&entry, &mut content.as_slice()).unwrap();
+        writer.finish().unwrap();
+        let bytes = std::fs::read(&source).unwrap();
+        std::fs::write(&source, &bytes[..bytes.len() / 2]).unwrap();
+        let archive = Archive::parse(&source).unwrap().unwrap();
+        let options = Options {
+            conflict: ConflictPolicy::Overwrite,
+            ..Default::default()
+        };
+        for (format, existing) in [
+            (Format::Zip, "data.zip"),
+            (Format::TarGz, "data.tar.gz"),
+            (Format::Directory, "data"),
+        ] {
+            let folder = temp.path().join(format!("out-{:?}", format));
+            create_dir_all(&folder).unwrap();
+            // 已存在的同名输出在转换失败时保持原样
+            std::fs::write(folder.join(existing), "earlier").unwrap();
+            archive
+                .convert(
+                    folder.clone(),
+                    Target::new(format),
+                    &options,
+                    &CancelToken::default(),
+                    |_| {},
+                )
+                .unwrap_err();
+            let names = read_dir(&folder)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .collect::<Vec<_>>();
+            assert_eq!(names, [existing]);
+            assert_eq!(
+                std::fs::read_to_string(folder.join(existing)).unwrap(),
+                "earlier"
+            );
+        }
+    }
+
+    #[test]
+    fn mirror_nested_root() {
+        let temp = TempDir::new();
+        let root = temp.path().join("in");
+        create_dir_all(root.join("a/b")).unwrap();
+        create_dir_all(root.join("a/c")).unwrap();
+        let source = root.join("a/b/data.zip");
+        let mut writer = codec::writer(&Target::new(Format::Zip), &source, None).unwrap();
+        let (entry, content) = entry(EntryKind::File, "a.txt", "hello", None);
+        writer.write(&entry, &mut content.as_bytes()).unwrap();
+        writer.finish().unwrap();
+        // 只有zip的魔数, 打开时就会失败
+        let broken = root.join("a/c/broken.zip");
+        std::fs::write(&broken, b"PK\x03\x04broken").unwrap();
+        let folder = temp.path().join("out");
+        create_dir_all(&folder).unwrap();
+        let options = Options {
+            mirror: true,
+            ..Default::default()
+        };
+        let convert = |path: &Path| {
+            Archive::parse(path)
+                .unwrap()
+                .unwrap()
+                .with_root(&root)
+                .convert(
+                    folder.clone(),
+                    Target::new(Format::TarGz),
+                    &options,
+                    &CancelToken::default(),
+                    |_| {},
+                )
+        };
+        let report = convert(&source).unwrap();
+        assert_eq!(report.output, folder.join("a/b/data.tar.gz"));
+        assert_eq!(
+            read_entries(Format::TarGz, &report.output),
+            [("a.txt".to_string(), EntryKind::File, "hello".to_string())]
+        );
+        // 无法读取的压缩包不会在输出目录中留下空目录
+        convert(&broken).unwrap_err();
+        assert!(!folder.join("a/c").exists());
+    }
+
+    #[test]
+    fn pack_folder_containing_output() {
+        let temp = TempDir::new();
+        let source = temp.path().join("data");
+        let folder = source.join("out");
+        create_dir_all(&folder).unwrap();
+        std::fs::write(source.join("a.txt"), "hello").unwrap();
+        // 同一批次中先转换的输出
+        std::fs::write(folder.join("earlier.zip"), "").unwrap();
+        let options = Options {
+            verify: true,
+            ..Default::default()
+        };
+        let report = Archive::unpacked(&source)
+            .unwrap()
+            .convert(
+                folder,
+                Target::new(Format::TarGz),
+                &options,
+                &CancelToken::default(),
+                |_| {},
+            )
+            .unwrap();
+        assert_eq!(
+            read_entries(Format::TarGz, &report.output),
+            [("a.txt".to_string(), EntryKind::File, "hello".to_string())]
+        );
+    }
+
+    #[cfg(feature = "7z")]
+    #[test]
+    fn pack_folder_into_itself_as_solid_7z() {
+        let temp = TempDir::new();
+        let source = temp.path().join("data");
+        create_dir_all(&source).unwrap();
+        std::fs::write(source.join("a.txt"), "hello").unwrap();
+        std::fs::write(source.join("b.txt"), "world").unwrap();
+        // 输出目录即源目录, 固实压缩的暂存文件与临时文件一样需要被跳过
+        let target = Target {
+            solid: true,
+            ..Target::new(Format::SevenZ)
+        };
+        let report = Archive::unpacked(&source)
+            .unwrap()
+            .convert(
+                source.clone(),
+                target,
+                &Options::default(),
+                &CancelToken::default(),
+                |_| {},
+            )
+            .unwrap();
+        assert_eq!(
+            read_entries(Format::SevenZ, &report.output),
+            [
+                ("a.txt".to_string(), EntryKind::File, "hello".to_string()),
+                ("b.txt".to_string(), EntryKind::File, "world".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+///
+/// 协作式的取消标记, 克隆后共享同一个状态
+///
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    ///
+    /// 已取消时返回[`Cancelled`]错误
+    ///
+    pub fn check(&self) -> anyhow::Result<()> {
+        match self.is_cancelled() {
+            true => Err(Cancelled.into()),
+            false => Ok(()),
+        }
+    }
+}
+
+///
+/// 任务被取消时返回的错误, 可以通过`anyhow::Error::is::<Cancelled>()`判断
+///
+#[derive(Debug, Copy, Clone)]
+pub struct Cancelled;
+
+impl Display for Cancelled {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "已取消")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+///
+/// 每次读取前检查是否已取消的读取器, 用于读取器内部在遍历条目之前的预读,
+/// 例如tar统计大小时的完整遍历和加密压缩包试解密时的解码
+///
+pub struct CancelRead<R> {
+    read: R,
+    cancel: CancelToken,
+}
+
+impl<R> CancelRead<R> {
+    pub fn new(read: R, cancel: CancelToken) -> Self {
+        Self { read, cancel }
+    }
+}
+
+impl<R: Read> Read for CancelRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.cancel.is_cancelled() {
+            return Err(std::io::Error::other(Cancelled));
+        }
+        self.read.read(buf)
+    }
+}
+
+impl<R: Seek> Seek for CancelRead<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.read.seek(pos)
+    }
+}
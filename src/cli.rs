@@ -0,0 +1,631 @@
+use abz::archive::{Archive, OutputExists, list_archive_entry};
+use abz::cancel::CancelToken;
+use abz::format::{Format, Level, NameEncoding, Target, ZipMethod};
+use abz::options::{ConflictPolicy, Options, UnsupportedPolicy};
+use abz::password::Password;
+use abz::scheduler::{Scheduler, default_concurrency};
+use abz::status::Status;
+use anyhow::{Context, anyhow, bail};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+用法: abz convert --to <格式> --out <目录> [--recursive] <文件或目录>...
+      abz convert --to <格式> --out <目录> --pack <目录或文件>...
+      abz test [--recursive] [--encoding <编码>] [--password <密码>] <文件或目录>...
+
+命令:
+    convert            转换为目标格式
+    test               解压所有条目以检查压缩包是否损坏, 不写入任何文件
+
+参数:
+    --to <格式>        目标格式, dir表示解压到保存目录下与压缩包同名的目录中
+    --map <源格式>=<目标格式>
+                       为指定格式的源单独设置目标格式, 可以重复指定, 例如 --map 7z=zip
+    --out <目录>       保存的目录
+    --method <方法>    zip的压缩方法: stored、deflate、bzip2、zstd、xz, 默认为deflate
+    --level <级别>     压缩级别, 默认使用各格式自身的默认级别
+    --solid            7z使用固实压缩, 所有文件作为一个整体压缩, 不能与加密同时使用
+    --copy             源格式与目标格式相同时直接复制文件, 默认按目标的压缩参数重新压缩
+    -r, --recursive    递归扫描输入目录
+    -p, --pack         将输入的目录或文件本身作为源打包, 而不是在其中查找压缩包或作为压缩包转换
+    --include <规则>   只保留匹配的条目, 可以重复指定, 规则匹配路径或文件名, 支持*和?
+    --exclude <规则>   去掉匹配的条目, 可以重复指定, 例如 --exclude .git --exclude '*.tmp'
+    -m, --mirror       在保存的目录中还原压缩包相对于输入目录的路径
+    -j, --jobs <数量>  同时转换的压缩包数量, 默认为CPU核心数
+    --encoding <auto|utf-8|gbk|shift-jis|cp437>
+                       zip中未标记为UTF-8的文件名的编码, 默认为auto, 即自动识别
+    --password <密码>  加密的zip或7z的密码, 可以重复指定, 依次尝试直到解密成功
+    --password-file <文件>
+                       从文件中读取密码列表, 每行一个
+    --encrypt <密码>   加密输出的压缩包, zip使用AES-256, 7z使用AES-256并加密文件名
+    --encrypt-file <文件>
+                       从文件的第一行读取加密输出的密码, 避免密码出现在命令历史中
+    --verify           转换后重新读取输出文件, 与源压缩包比较条目、大小和CRC32
+    --conflict <overwrite|skip|rename>
+                       输出文件已存在时的处理方式, 默认为rename, 即自动重命名为`data (1).tar.gz`
+    --unsupported <skip|materialize|fail>
+                       目标格式无法保存的条目(符号链接、设备文件等)的处理方式, 默认为skip
+    --hardlink <materialize|skip|fail>
+                       目标格式无法保存硬链接时的处理方式, 默认为materialize, 即写入其目标的内容
+    -h, --help         显示帮助信息";
+
+enum Command {
+    Convert {
+        target: Target,
+        /// 按源格式单独指定的目标格式
+        maps: Vec<(Format, Format)>,
+        out: PathBuf,
+        options: Options,
+    },
+    Test,
+}
+
+struct Args {
+    command: Command,
+    recursion: bool,
+    /// 输入的目录作为源打包
+    pack: bool,
+    /// zip文件名的编码
+    encoding: NameEncoding,
+    /// 加密压缩包依次尝试的密码
+    passwords: Vec<Password>,
+    jobs: usize,
+    inputs: Vec<PathBuf>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> anyhow::Result<Option<Self>> {
+        let test = match args.next().as_deref() {
+            Some("convert") => false,
+            Some("test") => true,
+            Some("-h" | "--help") | None => return Ok(None),
+            Some(command) => bail!("未知的命令: {}", command),
+        };
+        let mut format = None;
+        let mut maps = vec![];
+        let mut method = ZipMethod::default();
+        let mut level = Level::default();
+        let mut solid = false;
+        let mut out = None;
+        let mut recursion = false;
+        let mut pack = false;
+        let mut encoding = NameEncoding::default();
+        let mut passwords = vec![];
+        let mut options = None::<Options>;
+        let mut jobs = default_concurrency();
+        let mut inputs = vec![];
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--to" => {
+                    let value = args.next().ok_or_else(|| anyhow!("--to 缺少参数"))?;
+                    format = Some(value.parse::<Format>()?);
+                }
+                "--map" => {
+                    let value = args.next().ok_or_else(|| anyhow!("--map 缺少参数"))?;
+                    let (source, target) = value
+                        .split_once('=')
+                        .ok_or_else(|| anyhow!("--map 的格式为<源格式>=<目标格式>: {}", value))?;
+                    maps.push((source.parse::<Format>()?, target.parse::<Format>()?));
+                }
+                "--method" => {
+                    let value = args.next().ok_or_else(|| anyhow!("--method 缺少参数"))?;
+                    method = value.parse::<ZipMethod>()?;
+                }
+                "--level" => {
+                    let value = args.next().ok_or_else(|| anyhow!("--level 缺少参数"))?;
+                    level = value.parse::<Level>()?;
+                }
+                "--solid" => solid = true,
+                "--copy" => options.get_or_insert_default().copy = true,
+                "--out" => {
+                    let value = args.next().ok_or_else(|| anyhow!("--out 缺少参数"))?;
+                    out = Some(PathBuf::from(value));
+                }
+                "-r" | "--recursive" => recursion = true,
+                "-p" | "--pack" => pack = true,
+                "--encoding" => {
+                    let value = args.next().ok_or_else(|| anyhow!("--encoding 缺少参数"))?;
+                    encoding = value.parse::<NameEncoding>()?;
+                }
+                "--password" => {
+                    let value = args.next().ok_or_else(|| anyhow!("--password 缺少参数"))?;
+                    passwords.push(Password::from(value));
+                }
+                "--password-file" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| anyhow!("--password-file 缺少参数"))?;
+                    let content = std::fs::read_to_string(&value)
+                        .with_context(|| format!("无法读取密码文件: {}", value))?;
+                    passwords.extend(
+                        content
+                            .lines()
+                            .filter(|line| !line.is_empty())
+                            .map(Password::from),
+                    );
+                }
+                "--include" => {
+                    let value = args.next().ok_or_else(|| anyhow!("--include 缺少参数"))?;
+                    options.get_or_insert_default().filter.include.push(value);
+                }
+                "--exclude" => {
+                    let value = args.next().ok_or_else(|| anyhow!("--exclude 缺少参数"))?;
+                    options.get_or_insert_default().filter.exclude.push(value);
+                }
+                "--encrypt" => {
+                    let value = args.next().ok_or_else(|| anyhow!("--encrypt 缺少参数"))?;
+                    options.get_or_insert_default().password = Some(Password::from(value));
+                }
+                "--encrypt-file" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| anyhow!("--encrypt-file 缺少参数"))?;
+                    let content = std::fs::read_to_string(&value)
+                        .with_context(|| format!("无法读取密码文件: {}", value))?;
+                    let password = content.lines().next().unwrap_or_default();
+                    if password.is_empty() {
+                        bail!("密码文件的第一行为空: {}", value);
+                    }
+                    options.get_or_insert_default().password = Some(Password::from(password));
+                }
+                "-m" | "--mirror" => options.get_or_insert_default().mirror = true,
+                "--verify" => options.get_or_insert_default().verify = true,
+                "-j" | "--jobs" => {
+                    let value = args.next().ok_or_else(|| anyhow!("--jobs 缺少参数"))?;
+                    jobs = value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|jobs| *jobs > 0)
+                        .ok_or_else(|| anyhow!("无效的并发数量: {}", value))?;
+                }
+                "--conflict" => {
+                    let value = args.next().ok_or_else(|| anyhow!("--conflict 缺少参数"))?;
+                    options.get_or_insert_default().conflict =
+                        match value.parse::<ConflictPolicy>()? {
+                            ConflictPolicy::Ask => {
+                                bail!("命令行模式不支持询问, 请使用overwrite、skip或rename")
+                            }
+                            policy => policy,
+                        };
+                }
+                "--unsupported" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| anyhow!("--unsupported 缺少参数"))?;
+                    options.get_or_insert_default().unsupported =
+                        value.parse::<UnsupportedPolicy>()?;
+                }
+                "--hardlink" => {
+                    let value = args.next().ok_or_else(|| anyhow!("--hardlink 缺少参数"))?;
+                    options.get_or_insert_default().hardlink =
+                        value.parse::<UnsupportedPolicy>()?;
+                }
+                "-h" | "--help" => return Ok(None),
+                _ if arg.starts_with('-') => bail!("未知的参数: {}", arg),
+                _ => inputs.push(PathBuf::from(arg)),
+            }
+        }
+        if inputs.is_empty() {
+            bail!("未指定任何输入文件");
+        }
+        let command = if test {
+            if format.is_some()
+                || out.is_some()
+                || options.is_some()
+                || pack
+                || solid
+                || !maps.is_empty()
+            {
+                bail!("test 命令只支持 --recursive、--jobs、--encoding 和 --password 参数");
+            }
+            Command::Test
+        } else {
+            let target = Target {
+                format: format.ok_or_else(|| anyhow!("未指定目标格式 --to"))?,
+                method,
+                level,
+                solid,
+            };
+            target.validate()?;
+            let encrypt = options
+                .as_ref()
+                .is_some_and(|options| options.password.is_some());
+            if encrypt && !target.format.encryptable() {
+                bail!("{}不支持加密, 只有zip和7z可以设置密码", target.format);
+            }
+            // 固实压缩会把未加密的条目内容暂存到磁盘, 异常退出时可能残留明文
+            if encrypt && solid {
+                bail!("--solid 不能与 --encrypt 或 --encrypt-file 同时使用");
+            }
+            // 单独设置的目标格式同样需要支持加密, 否则这些压缩包会在转换时逐个失败
+            if let Some((source, mapped)) = maps
+                .iter()
+                .find(|(_, mapped)| encrypt && !mapped.encryptable())
+            {
+                bail!(
+                    "--map {}={}: {}不支持加密, 只有zip和7z可以设置密码",
+                    source,
+                    mapped,
+                    mapped
+                );
+            }
+            Command::Convert {
+                target,
+                maps,
+                out: out.ok_or_else(|| anyhow!("未指定保存的目录 --out"))?,
+                options: options.unwrap_or_default(),
+            }
+        };
+        Ok(Some(Args {
+            command,
+            recursion,
+            pack,
+            encoding,
+            passwords,
+            jobs,
+            inputs,
+        }))
+    }
+}
+
+///
+/// 以命令行模式运行, 任意压缩包转换失败时返回非零的退出码
+///
+pub fn run(args: impl Iterator<Item = String>) -> ExitCode {
+    let args = match Args::parse(args) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let result = match &args.command {
+        Command::Convert {
+            target,
+            maps,
+            out,
+            options,
+        } => convert(&args, *target, maps, out, options),
+        Command::Test => test(&args),
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("{:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+///
+/// 收集所有输入中的压缩包, 无法读取的输入、文件或子目录输出到标准错误后跳过, 不影响其它输入
+///
+/// returns: (Vec<Archive>, usize) 压缩包以及无法读取的路径数量
+///
+fn collect_archives(args: &Args) -> (Vec<Archive>, usize) {
+    let mut archives = vec![];
+    let mut unreadable = 0;
+    let mut report = |path: &Path, e: anyhow::Error| {
+        eprintln!("无法读取 {}: {:#}", path.display(), e);
+        unreadable += 1;
+    };
+    for input in &args.inputs {
+        if args.pack {
+            match Archive::unpacked(input) {
+                Ok(archive) => archives.push(archive),
+                Err(e) => report(input, e),
+            }
+        } else if input.is_dir() {
+            scan_dir(input, input, args.recursion, &mut archives, &mut report);
+        } else {
+            // 直接指定的其它文件作为源打包, 测试时跳过
+            match (Archive::parse(input), &args.command) {
+                (Ok(Some(archive)), _) => archives.push(archive),
+                (Ok(None), Command::Test) => eprintln!("跳过不支持的文件: {}", input.display()),
+                (Ok(None), _) => match Archive::unpacked(input) {
+                    Ok(archive) => archives.push(archive),
+                    Err(e) => report(input, e),
+                },
+                (Err(e), _) => report(input, e.into()),
+            }
+        }
+    }
+    for archive in &mut archives {
+        archive.encoding = args.encoding;
+        archive.passwords = args.passwords.clone();
+        if let Some(warning) = archive.mislabeled_warning() {
+            eprintln!("警告: {}", warning);
+        }
+    }
+    (archives, unreadable)
+}
+
+///
+/// 扫描目录中的压缩包, 无法读取的文件或子目录交给`report`处理后继续扫描
+///
+/// # Arguments
+///
+/// * `dir`: 扫描的目录
+/// * `root`: 输入的目录, 用于还原目录结构
+/// * `recursion`: 是否递归子目录
+/// * `archives`: 找到的压缩包
+/// * `report`: 处理无法读取的路径
+///
+fn scan_dir(
+    dir: &Path,
+    root: &Path,
+    recursion: bool,
+    archives: &mut Vec<Archive>,
+    report: &mut dyn FnMut(&Path, anyhow::Error),
+) {
+    let entries = match list_archive_entry(dir, false) {
+        Ok(entries) => entries,
+        Err(e) => return report(dir, e),
+    };
+    for entry in entries {
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => {
+                if recursion {
+                    scan_dir(&path, root, recursion, archives, report);
+                }
+            }
+            Ok(_) => match Archive::parse(&path) {
+                Ok(Some(archive)) => archives.push(archive.with_root(root)),
+                Ok(None) => {}
+                Err(e) => report(&path, e.into()),
+            },
+            Err(e) => report(&path, e.into()),
+        }
+    }
+}
+
+fn convert(
+    args: &Args,
+    target: Target,
+    maps: &[(Format, Format)],
+    out: &Path,
+    options: &Options,
+) -> anyhow::Result<bool> {
+    let (mut archives, unreadable) = collect_archives(args);
+    for archive in &mut archives {
+        archive.target = maps
+            .iter()
+            .find(|(source, _)| *source == archive.format)
+            .map(|(_, target)| *target);
+    }
+    std::fs::create_dir_all(out)?;
+    let total = archives.len();
+    let cancel = CancelToken::default();
+    let statuses = Scheduler::new(args.jobs).run(archives, |index, archive| {
+        let prefix = format!("[{}/{}] {}", index + 1, total, archive.path.display());
+        let result = archive.convert(
+            out.to_path_buf(),
+            archive.resolve_target(target),
+            options,
+            &cancel,
+            progress(&prefix),
+        );
+        let status = match result {
+            Ok(report) => Status::from(&report),
+            Err(e) if e.is::<OutputExists>() => Status::Skipped(e.to_string().into()),
+            Err(e) => Status::Failed(format!("{:#}", e).into()),
+        };
+        println!("{} {}", prefix, status);
+        status
+    });
+    Ok(summarize(&statuses, unreadable))
+}
+
+fn test(args: &Args) -> anyhow::Result<bool> {
+    let (archives, unreadable) = collect_archives(args);
+    let total = archives.len();
+    let cancel = CancelToken::default();
+    let statuses = Scheduler::new(args.jobs).run(archives, |index, archive| {
+        let prefix = format!("[{}/{}] {}", index + 1, total, archive.path.display());
+        let status = match archive.test(&cancel, progress(&prefix)) {
+            Ok(()) => Status::Success,
+            Err(e) => Status::Failed(format!("{:#}", e).into()),
+        };
+        println!("{} {}", prefix, status);
+        status
+    });
+    Ok(summarize(&statuses, unreadable))
+}
+
+///
+/// 输出处理进度, 每个阶段每增加10%输出一次, 避免刷屏
+///
+fn progress(prefix: &str) -> impl FnMut(Status) {
+    let mut step = (0, 0);
+    move |status| {
+        let (phase, ratio) = match status {
+            Status::Processing(ratio) => (0, ratio),
+            Status::Verifying(ratio) => (1, ratio),
+            _ => return,
+        };
+        let current = (phase, (ratio * 10.) as i32);
+        if current > step {
+            step = current;
+            println!("{} {} {:.0}%", prefix, status, ratio * 100.);
+        }
+    }
+}
+
+///
+/// 输出汇总信息, 没有失败的压缩包且所有输入都能读取时返回true
+///
+/// # Arguments
+///
+/// * `statuses`: 每个压缩包的处理结果
+/// * `unreadable`: 扫描时无法读取的路径数量, 计入失败
+///
+/// returns: bool
+///
+fn summarize(statuses: &[Status], unreadable: usize) -> bool {
+    let failed = statuses
+        .iter()
+        .filter(|status| matches!(status, Status::Failed(_)))
+        .count();
+    let skipped = statuses
+        .iter()
+        .filter(|status| matches!(status, Status::Skipped(_)))
+        .count();
+    println!(
+        "完成: 成功 {}, 跳过 {}, 失败 {}",
+        statuses.len() - failed - skipped,
+        skipped,
+        failed + unreadable
+    );
+    failed + unreadable == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> anyhow::Result<Option<Args>> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn parse_error(args: &[&str]) -> String {
+        parse(args).err().unwrap().to_string()
+    }
+
+    fn convert(args: &[&str]) -> (Target, Vec<(Format, Format)>, PathBuf, Options) {
+        let args = [
+            &["convert", "--to", "zip", "--out", "out"],
+            args,
+            &["a.tar.gz"],
+        ]
+        .concat();
+        match parse(&args).unwrap().unwrap().command {
+            Command::Convert {
+                target,
+                maps,
+                out,
+                options,
+            } => (target, maps, out, options),
+            Command::Test => panic!("not a convert command"),
+        }
+    }
+
+    #[test]
+    fn convert_defaults() {
+        let (target, maps, out, options) = convert(&[]);
+        assert_eq!(target, Target::new(Format::Zip));
+        assert!(maps.is_empty());
+        assert_eq!(out, PathBuf::from("out"));
+        assert_eq!(options.conflict, ConflictPolicy::Rename);
+        assert_eq!(options.hardlink, UnsupportedPolicy::Materialize);
+    }
+
+    #[test]
+    fn map_formats() {
+        let (_, maps, _, _) = convert(&["--map", "tar.gz=dir", "--map", "zip=tar.gz"]);
+        assert_eq!(
+            maps,
+            [
+                (Format::TarGz, Format::Directory),
+                (Format::Zip, Format::TarGz)
+            ]
+        );
+        assert!(parse_error(&["convert", "--map", "tar.gz", "a.zip"]).contains("--map"));
+        assert!(parse_error(&["convert", "--map", "tar.gz=rar", "a.zip"]).contains("rar"));
+    }
+
+    #[test]
+    fn map_rejects_unencryptable_target() {
+        let error = parse_error(&[
+            "convert",
+            "--to",
+            "zip",
+            "--out",
+            "out",
+            "--encrypt",
+            "secret",
+            "--map",
+            "zip=tar.gz",
+            "a.zip",
+        ]);
+        assert!(error.contains("--map zip=tar.gz"), "{}", error);
+    }
+
+    #[test]
+    fn solid_rejects_encryption() {
+        let error = parse_error(&[
+            "convert",
+            "--to",
+            "zip",
+            "--out",
+            "out",
+            "--solid",
+            "--encrypt",
+            "secret",
+            "a.zip",
+        ]);
+        assert!(error.contains("--solid"), "{}", error);
+    }
+
+    #[test]
+    fn conflict_policies() {
+        for policy in [
+            ConflictPolicy::Overwrite,
+            ConflictPolicy::Skip,
+            ConflictPolicy::Rename,
+        ] {
+            let (_, _, _, options) = convert(&["--conflict", policy.name()]);
+            assert_eq!(options.conflict, policy);
+        }
+        // 命令行无法询问用户
+        assert!(parse_error(&["convert", "--conflict", "ask", "a.zip"]).contains("不支持询问"));
+        assert!(parse_error(&["convert", "--conflict", "keep", "a.zip"]).contains("keep"));
+    }
+
+    #[test]
+    fn unknown_arguments() {
+        assert_eq!(parse_error(&["extract", "a.zip"]), "未知的命令: extract");
+        assert_eq!(
+            parse_error(&["convert", "--bogus", "a.zip"]),
+            "未知的参数: --bogus"
+        );
+    }
+
+    #[test]
+    fn missing_values() {
+        for flag in ["--to", "--out", "--map", "--level", "--conflict", "--jobs"] {
+            assert_eq!(
+                parse_error(&["convert", flag]),
+                format!("{} 缺少参数", flag)
+            );
+        }
+        assert_eq!(
+            parse_error(&["convert", "--to", "zip", "a.zip"]),
+            "未指定保存的目录 --out"
+        );
+        assert_eq!(
+            parse_error(&["convert", "--to", "zip", "--out", "out"]),
+            "未指定任何输入文件"
+        );
+        assert!(parse_error(&["convert", "--jobs", "0", "a.zip"]).contains("0"));
+    }
+
+    #[test]
+    fn help_and_test_command() {
+        assert!(parse(&[]).unwrap().is_none());
+        assert!(parse(&["convert", "--help"]).unwrap().is_none());
+        let args = parse(&["test", "-r", "--password", "a", "--password", "b", "dir"])
+            .unwrap()
+            .unwrap();
+        assert!(matches!(args.command, Command::Test));
+        assert!(args.recursion);
+        assert_eq!(args.passwords, [Password::from("a"), Password::from("b")]);
+        assert!(parse_error(&["test", "--to", "zip", "a.zip"]).starts_with("test 命令"));
+    }
+}
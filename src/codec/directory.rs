@@ -0,0 +1,501 @@
+use crate::codec::{ArchiveReader, ArchiveWriter, Attribute, Entry, EntryKind, TEMP_EXTENSION};
+use anyhow::{anyhow, bail};
+use std::collections::HashMap;
+use std::fs::{
+    File, FileTimes, create_dir, create_dir_all, metadata, remove_file, symlink_metadata,
+};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+///
+/// 按压缩包条目的形式读取目录, 条目路径相对于该目录
+///
+/// 源为单个文件时只有一个以文件名为路径的条目, 转换时写入的临时文件不会作为条目
+///
+pub struct DirectoryReader {
+    root: PathBuf,
+    /// 条目路径相对的目录, 源为单个文件时为其所在的目录
+    base: PathBuf,
+    /// 遍历时跳过的目录, 输出目录位于源目录之中时为输出目录
+    skip: Option<PathBuf>,
+}
+
+impl DirectoryReader {
+    ///
+    /// 打开目录或文件
+    ///
+    /// # Arguments
+    ///
+    /// * `source`: 源目录或文件
+    /// * `skip`: 遍历时跳过的目录, 路径需要以`source`开头
+    ///
+    /// returns: Result<DirectoryReader, Error>
+    ///
+    pub fn open(source: &Path, skip: Option<&Path>) -> anyhow::Result<Self> {
+        let metadata = metadata(source)?;
+        let base = if metadata.is_dir() {
+            source
+        } else if metadata.is_file() {
+            source.parent().unwrap_or(Path::new(""))
+        } else {
+            bail!("不是目录或文件: {}", source.display());
+        };
+        Ok(Self {
+            root: source.to_path_buf(),
+            base: base.to_path_buf(),
+            skip: skip.map(Path::to_path_buf),
+        })
+    }
+
+    ///
+    /// 依次访问源中的每个文件, 源为单个文件时只访问该文件, 跟随选择的文件本身的符号链接
+    ///
+    fn walk_root(
+        &self,
+        visit: &mut dyn FnMut(&Path, &std::fs::Metadata) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let metadata = metadata(&self.root)?;
+        match metadata.is_dir() {
+            true => self.walk(&self.root, visit),
+            false => visit(&self.root, &metadata),
+        }
+    }
+
+    ///
+    /// 按文件名顺序深度优先遍历目录, 不跟随符号链接, 跳过需要跳过的目录和转换时的临时文件
+    ///
+    fn walk(
+        &self,
+        dir: &Path,
+        visit: &mut dyn FnMut(&Path, &std::fs::Metadata) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let mut children = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        children.retain(|child| {
+            Some(child) != self.skip.as_ref()
+                && child
+                    .extension()
+                    .is_none_or(|extension| extension != TEMP_EXTENSION)
+        });
+        children.sort();
+        for child in children {
+            let metadata = symlink_metadata(&child)?;
+            visit(&child, &metadata)?;
+            if metadata.is_dir() {
+                self.walk(&child, visit)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ArchiveReader for DirectoryReader {
+    fn size(&mut self) -> anyhow::Result<u64> {
+        let mut size = 0;
+        self.walk_root(&mut |_, metadata| {
+            if metadata.is_file() {
+                size += metadata.len();
+            }
+            Ok(())
+        })?;
+        Ok(size)
+    }
+
+    fn for_each(
+        &mut self,
+        visit: &mut dyn FnMut(Entry, &mut dyn Read) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        // 同一个文件的多个路径, 第一个之后的作为指向它的硬链接
+        let mut inodes = HashMap::new();
+        self.walk_root(&mut |path, metadata| {
+            let relative = path
+                .strip_prefix(&self.base)?
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let file_type = metadata.file_type();
+            let (kind, link) = if file_type.is_dir() {
+                (EntryKind::Directory, None)
+            } else if file_type.is_symlink() {
+                let link = std::fs::read_link(path)?
+                    .to_string_lossy()
+                    .replace('\\', "/");
+                (EntryKind::Symlink, Some(link))
+            } else if file_type.is_file() {
+                match inode(metadata) {
+                    Some(inode) => match inodes.get(&inode) {
+                        Some(first) => (EntryKind::Hardlink, Some(String::clone(first))),
+                        None => {
+                            inodes.insert(inode, relative.clone());
+                            (EntryKind::File, None)
+                        }
+                    },
+                    None => (EntryKind::File, None),
+                }
+            } else {
+                let kind = special_kind(metadata)
+                    .ok_or_else(|| anyhow!("无法识别的文件类型: {}", relative))?;
+                (kind, None)
+            };
+            let entry = Entry {
+                path: relative,
+                kind,
+                size: match kind {
+                    EntryKind::File => metadata.len(),
+                    _ => 0,
+                },
+                mode: mode(metadata),
+                modified: metadata.modified().ok(),
+                accessed: metadata.accessed().ok(),
+                uid: owner(metadata).map(|(uid, _)| uid),
+                gid: owner(metadata).map(|(_, gid)| gid),
+                link,
+                ..Default::default()
+            };
+            match kind {
+                EntryKind::File => visit(entry, &mut File::open(path)?),
+                _ => visit(entry, &mut std::io::empty()),
+            }
+        })
+    }
+}
+
+///
+/// 将条目解压到目录中
+///
+/// 条目路径中的`..`和绝对路径会被拒绝, 也不会经由已解压的符号链接写入, 保证不会写到目录之外
+///
+pub struct DirectoryWriter {
+    root: PathBuf,
+    /// 目录的权限和时间在写完其中的文件后才设置, 避免只读目录无法写入或修改时间被覆盖
+    directories: Vec<(PathBuf, Entry)>,
+}
+
+impl DirectoryWriter {
+    ///
+    /// 创建解压目录, 目录已存在时返回错误
+    ///
+    pub fn create(target: &Path) -> anyhow::Result<Self> {
+        create_dir(target)?;
+        Ok(Self {
+            root: target.to_path_buf(),
+            directories: vec![],
+        })
+    }
+
+    ///
+    /// 计算条目在解压目录中的路径
+    ///
+    fn resolve(&self, path: &str) -> anyhow::Result<PathBuf> {
+        let mut resolved = self.root.clone();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(name) => resolved.push(name),
+                Component::CurDir => {}
+                Component::ParentDir => bail!("条目路径包含`..`, 拒绝解压到目录之外: {}", path),
+                Component::RootDir | Component::Prefix(_) => {
+                    bail!("条目使用了绝对路径, 拒绝解压: {}", path)
+                }
+            }
+        }
+        // 符号链接可能指向目录之外, 不能经由其写入
+        for ancestor in resolved.ancestors().skip(1) {
+            if ancestor == self.root {
+                break;
+            }
+            if symlink_metadata(ancestor).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+                bail!("条目位于符号链接中, 拒绝解压: {}", path);
+            }
+        }
+        Ok(resolved)
+    }
+}
+
+impl ArchiveWriter for DirectoryWriter {
+    fn attributes(&self) -> &'static [Attribute] {
+        #[cfg(unix)]
+        return &[Attribute::Mode, Attribute::Modified, Attribute::Accessed];
+        #[cfg(not(unix))]
+        return &[Attribute::Modified, Attribute::Accessed];
+    }
+
+    fn kinds(&self) -> &'static [EntryKind] {
+        #[cfg(unix)]
+        return &[
+            EntryKind::File,
+            EntryKind::Directory,
+            EntryKind::Symlink,
+            EntryKind::Hardlink,
+        ];
+        #[cfg(not(unix))]
+        return &[EntryKind::File, EntryKind::Directory, EntryKind::Hardlink];
+    }
+
+    fn write(&mut self, entry: &Entry, data: &mut dyn Read) -> anyhow::Result<()> {
+        let path = self.resolve(&entry.path)?;
+        if path == self.root {
+            return match entry.kind {
+                EntryKind::Directory => Ok(()),
+                _ => Err(anyhow!("条目路径为空")),
+            };
+        }
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        // 同名条目以后出现的为准, 先删除已有的文件或链接, 避免经由链接写到其它位置
+        if symlink_metadata(&path).is_ok_and(|metadata| !metadata.is_dir()) {
+            remove_file(&path)?;
+        }
+        if entry.kind == EntryKind::Directory {
+            create_dir_all(&path)?;
+            self.directories.push((path, entry.clone()));
+            return Ok(());
+        }
+        let link = || {
+            entry
+                .link
+                .as_deref()
+                .ok_or_else(|| anyhow!("链接缺少目标路径: {}", entry.path))
+        };
+        match entry.kind {
+            #[cfg(unix)]
+            EntryKind::Symlink => std::os::unix::fs::symlink(link()?, &path)?,
+            EntryKind::Hardlink => std::fs::hard_link(self.resolve(link()?)?, &path)?,
+            _ => {
+                let mut file = File::create(&path)?;
+                std::io::copy(data, &mut file)?;
+                set_attributes(&file, entry)?;
+                file.sync_all()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> anyhow::Result<()> {
+        // 子目录先于父目录设置, 父目录的修改时间不会再被改变;
+        // Windows下无法直接打开目录, 不设置目录的属性
+        if cfg!(unix) {
+            for (path, entry) in self.directories.iter().rev() {
+                set_attributes(&File::open(path)?, entry)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+///
+/// 设置文件或目录的权限和时间
+///
+fn set_attributes(file: &File, entry: &Entry) -> anyhow::Result<()> {
+    let mut times = FileTimes::new();
+    if let Some(modified) = entry.modified {
+        times = times.set_modified(modified);
+    }
+    if let Some(accessed) = entry.accessed.or(entry.modified) {
+        times = times.set_accessed(accessed);
+    }
+    file.set_times(times)?;
+    #[cfg(unix)]
+    if let Some(mode) = entry.mode {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(mode))?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn mode(metadata: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn mode(_: &std::fs::Metadata) -> Option<u32> {
+    None
+}
+
+///
+/// 文件所在设备和inode号, 只统计有多个硬链接的文件
+///
+#[cfg(unix)]
+fn inode(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn inode(_: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(unix)]
+fn owner(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.uid() as u64, metadata.gid() as u64))
+}
+
+#[cfg(not(unix))]
+fn owner(_: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(unix)]
+fn special_kind(metadata: &std::fs::Metadata) -> Option<EntryKind> {
+    use std::os::unix::fs::FileTypeExt;
+    let file_type = metadata.file_type();
+    if file_type.is_fifo() {
+        Some(EntryKind::Fifo)
+    } else if file_type.is_char_device() {
+        Some(EntryKind::CharDevice)
+    } else if file_type.is_block_device() {
+        Some(EntryKind::BlockDevice)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+fn special_kind(_: &std::fs::Metadata) -> Option<EntryKind> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use std::fs::{read_to_string, write};
+
+    fn file(path: &str, content: &str) -> (Entry, String) {
+        let entry = Entry {
+            path: path.to_string(),
+            kind: EntryKind::File,
+            size: content.len() as u64,
+            ..Default::default()
+        };
+        (entry, content.to_string())
+    }
+
+    fn write_file(writer: &mut DirectoryWriter, path: &str, content: &str) -> anyhow::Result<()> {
+        let (entry, content) = file(path, content);
+        writer.write(&entry, &mut content.as_bytes())
+    }
+
+    #[test]
+    fn resolve_normal_path() {
+        let temp = TempDir::new();
+        let root = temp.path().join("out");
+        let writer = DirectoryWriter::create(&root).unwrap();
+        assert_eq!(writer.resolve("a/./b").unwrap(), root.join("a").join("b"));
+        assert_eq!(writer.resolve("").unwrap(), root);
+    }
+
+    #[test]
+    fn reject_parent_dir() {
+        let temp = TempDir::new();
+        let mut writer = DirectoryWriter::create(&temp.path().join("out")).unwrap();
+        assert!(writer.resolve("../escape").is_err());
+        assert!(writer.resolve("a/../../escape").is_err());
+        // 即使最终仍在目录之内, 包含`..`的路径也会被拒绝
+        assert!(writer.resolve("a/../b").is_err());
+        assert!(write_file(&mut writer, "../escape", "x").is_err());
+        assert!(!temp.path().join("escape").exists());
+    }
+
+    #[test]
+    fn reject_absolute_path() {
+        let temp = TempDir::new();
+        let mut writer = DirectoryWriter::create(&temp.path().join("out")).unwrap();
+        let absolute = temp.path().join("escape");
+        assert!(writer.resolve(&absolute.to_string_lossy()).is_err());
+        assert!(writer.resolve("/escape").is_err());
+        assert!(write_file(&mut writer, &absolute.to_string_lossy(), "x").is_err());
+        assert!(!absolute.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reject_symlinked_ancestor() {
+        let temp = TempDir::new();
+        let outside = temp.path().join("outside");
+        create_dir(&outside).unwrap();
+        let mut writer = DirectoryWriter::create(&temp.path().join("out")).unwrap();
+        let link = Entry {
+            path: "link".to_string(),
+            kind: EntryKind::Symlink,
+            link: Some(outside.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        writer.write(&link, &mut std::io::empty()).unwrap();
+        assert!(writer.resolve("link/escape").is_err());
+        assert!(write_file(&mut writer, "link/escape", "x").is_err());
+        assert!(write_file(&mut writer, "link/sub/escape", "x").is_err());
+        assert!(!outside.join("escape").exists());
+        assert!(!outside.join("sub").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replace_existing_symlink() {
+        let temp = TempDir::new();
+        let outside = temp.path().join("outside.txt");
+        write(&outside, "outside").unwrap();
+        let root = temp.path().join("out");
+        let mut writer = DirectoryWriter::create(&root).unwrap();
+        let link = Entry {
+            path: "same".to_string(),
+            kind: EntryKind::Symlink,
+            link: Some(outside.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        writer.write(&link, &mut std::io::empty()).unwrap();
+        // 同名的文件替换链接本身, 不会经由链接写入其目标
+        write_file(&mut writer, "same", "inside").unwrap();
+        assert_eq!(read_to_string(&outside).unwrap(), "outside");
+        assert!(!symlink_metadata(root.join("same")).unwrap().is_symlink());
+        assert_eq!(read_to_string(root.join("same")).unwrap(), "inside");
+    }
+
+    #[test]
+    fn replace_existing_file() {
+        let temp = TempDir::new();
+        let root = temp.path().join("out");
+        let mut writer = DirectoryWriter::create(&root).unwrap();
+        write_file(&mut writer, "same", "first").unwrap();
+        write_file(&mut writer, "same", "second").unwrap();
+        assert_eq!(read_to_string(root.join("same")).unwrap(), "second");
+        // 同名的目录替换已有的文件
+        let directory = Entry {
+            path: "same".to_string(),
+            kind: EntryKind::Directory,
+            ..Default::default()
+        };
+        writer.write(&directory, &mut std::io::empty()).unwrap();
+        assert!(root.join("same").is_dir());
+    }
+
+    #[test]
+    fn skip_output_and_temp_files() {
+        let temp = TempDir::new();
+        let root = temp.path().join("source");
+        std::fs::create_dir_all(root.join("out")).unwrap();
+        std::fs::create_dir_all(root.join(".out.1-0.abz-tmp")).unwrap();
+        write(root.join("a.txt"), "a").unwrap();
+        write(root.join("out/earlier.zip"), "").unwrap();
+        write(root.join(".source.zip.1-1.abz-tmp"), "").unwrap();
+        let paths = |skip: Option<&Path>| {
+            let mut paths = vec![];
+            DirectoryReader::open(&root, skip)
+                .unwrap()
+                .for_each(&mut |entry, _| {
+                    paths.push(entry.path);
+                    Ok(())
+                })
+                .unwrap();
+            paths
+        };
+        assert_eq!(paths(None), ["a.txt", "out", "out/earlier.zip"]);
+        assert_eq!(paths(Some(&root.join("out"))), ["a.txt"]);
+    }
+}
@@ -0,0 +1,569 @@
+use crate::cancel::CancelToken;
+use crate::format::{Format, NameEncoding, Target};
+use crate::password::Password;
+use anyhow::bail;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::time::SystemTime;
+
+mod directory;
+#[cfg(feature = "7z")]
+mod seven_z;
+mod tar;
+mod zip;
+
+///
+/// 压缩包中的条目
+///
+#[derive(Debug, Clone, Default)]
+pub struct Entry {
+    /// 条目路径, 使用`/`分隔, 目录不以`/`结尾
+    pub path: String,
+    pub kind: EntryKind,
+    /// 解压后的大小, 目录为0
+    pub size: u64,
+    /// 源格式中保存的内容CRC32, 用于校验读取到的内容, 源格式没有保存时为空
+    pub crc32: Option<u32>,
+    /// unix权限位, 源格式不支持时为空
+    pub mode: Option<u32>,
+    /// 修改时间
+    pub modified: Option<SystemTime>,
+    /// 访问时间
+    pub accessed: Option<SystemTime>,
+    pub uid: Option<u64>,
+    pub gid: Option<u64>,
+    /// 所有者的用户名
+    pub user: Option<String>,
+    /// 所有者的组名
+    pub group: Option<String>,
+    /// 符号链接或硬链接的目标路径
+    pub link: Option<String>,
+    /// 设备文件的主次设备号
+    pub device: Option<(u32, u32)>,
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum EntryKind {
+    #[default]
+    File,
+    Directory,
+    Symlink,
+    Hardlink,
+    Fifo,
+    CharDevice,
+    BlockDevice,
+    /// 无法识别的条目类型, 保存tar头部中的类型标记, 任何目标格式都无法保存
+    Unknown(u8),
+}
+
+impl Display for EntryKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EntryKind::File => write!(f, "文件"),
+            EntryKind::Directory => write!(f, "目录"),
+            EntryKind::Symlink => write!(f, "符号链接"),
+            EntryKind::Hardlink => write!(f, "硬链接"),
+            EntryKind::Fifo => write!(f, "命名管道"),
+            EntryKind::CharDevice => write!(f, "字符设备"),
+            EntryKind::BlockDevice => write!(f, "块设备"),
+            EntryKind::Unknown(flag) => write!(f, "未知类型({})", *flag as char),
+        }
+    }
+}
+
+///
+/// 条目的元数据属性, 用于报告目标格式无法保留的属性
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Attribute {
+    Mode,
+    Modified,
+    Accessed,
+    Owner,
+    OwnerName,
+}
+
+impl Display for Attribute {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Attribute::Mode => write!(f, "权限"),
+            Attribute::Modified => write!(f, "修改时间"),
+            Attribute::Accessed => write!(f, "访问时间"),
+            Attribute::Owner => write!(f, "uid/gid"),
+            Attribute::OwnerName => write!(f, "用户名/组名"),
+        }
+    }
+}
+
+impl Entry {
+    pub fn is_dir(&self) -> bool {
+        self.kind == EntryKind::Directory
+    }
+
+    ///
+    /// 获取权限位, 源格式未提供时按条目类型返回默认值
+    ///
+    pub fn mode_or_default(&self) -> u32 {
+        self.mode.unwrap_or(match self.kind {
+            EntryKind::Directory => 0o755,
+            EntryKind::Symlink => 0o777,
+            _ => 0o644,
+        })
+    }
+
+    ///
+    /// 条目中实际携带的属性
+    ///
+    pub fn attributes(&self) -> Vec<Attribute> {
+        [
+            (Attribute::Mode, self.mode.is_some()),
+            (Attribute::Modified, self.modified.is_some()),
+            (Attribute::Accessed, self.accessed.is_some()),
+            (Attribute::Owner, self.uid.is_some() || self.gid.is_some()),
+            (
+                Attribute::OwnerName,
+                self.user.is_some() || self.group.is_some(),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(attribute, present)| present.then_some(attribute))
+        .collect()
+    }
+}
+
+///
+/// 转换时写入的临时文件的扩展名, 打包目录时会跳过这些文件
+///
+pub(crate) const TEMP_EXTENSION: &str = "abz-tmp";
+
+///
+/// 按顺序读取压缩包中的条目
+///
+pub trait ArchiveReader {
+    ///
+    /// 解压后的总大小, 用于计算进度
+    ///
+    fn size(&mut self) -> anyhow::Result<u64>;
+
+    ///
+    /// 依次访问压缩包中的每个条目, 只能调用一次
+    ///
+    /// # Arguments
+    ///
+    /// * `visit`: 条目访问函数, 返回错误时中止读取
+    ///
+    /// returns: Result<(), Error>
+    ///
+    fn for_each(
+        &mut self,
+        visit: &mut dyn FnMut(Entry, &mut dyn Read) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()>;
+}
+
+///
+/// 依次向压缩包中写入条目
+///
+pub trait ArchiveWriter {
+    ///
+    /// 目标格式能够保留的属性
+    ///
+    fn attributes(&self) -> &'static [Attribute];
+
+    ///
+    /// 目标格式能够保存的条目类型
+    ///
+    fn kinds(&self) -> &'static [EntryKind];
+
+    ///
+    /// 写入条目时无法保留的属性, 默认为条目中不在[`attributes`](Self::attributes)内的属性
+    ///
+    fn dropped(&self, entry: &Entry) -> Vec<Attribute> {
+        entry
+            .attributes()
+            .into_iter()
+            .filter(|attribute| !self.attributes().contains(attribute))
+            .collect()
+    }
+
+    ///
+    /// 写入一个条目, 除普通文件外的条目都会忽略`data`
+    ///
+    fn write(&mut self, entry: &Entry, data: &mut dyn Read) -> anyhow::Result<()>;
+
+    ///
+    /// 写入压缩包的结尾并同步到磁盘
+    ///
+    fn finish(self: Box<Self>) -> anyhow::Result<()>;
+}
+
+///
+/// 打开压缩包读取器
+///
+/// # Arguments
+///
+/// * `format`: 压缩包格式
+/// * `source`: 源文件, 格式为目录时为源目录
+/// * `encoding`: zip文件名的编码, 其它格式忽略
+/// * `passwords`: 加密的zip和7z依次尝试的密码, 其它格式忽略
+/// * `cancel`: 取消标记, 取消后打开时的试解密、tar统计大小时的遍历以及读取条目都会返回错误
+///
+/// returns: Result<Box<dyn ArchiveReader>, Error>
+///
+pub fn reader(
+    format: Format,
+    source: &Path,
+    encoding: NameEncoding,
+    passwords: &[Password],
+    cancel: &CancelToken,
+) -> anyhow::Result<Box<dyn ArchiveReader>> {
+    Ok(match format {
+        Format::Zip => Box::new(zip::ZipReader::open(source, encoding, passwords, cancel)?),
+        Format::TarGz => Box::new(tar::TarReader::open_gz(source, cancel)?),
+        #[cfg(feature = "bz2")]
+        Format::TarBz2 => Box::new(tar::TarReader::open_bz2(source, cancel)?),
+        #[cfg(feature = "xz")]
+        Format::TarXz => Box::new(tar::TarReader::open_xz(source, cancel)?),
+        #[cfg(feature = "zst")]
+        Format::TarZst => Box::new(tar::TarReader::open_zst(source, cancel)?),
+        #[cfg(feature = "7z")]
+        Format::SevenZ => Box::new(seven_z::SevenZReader::open(source, passwords, cancel)?),
+        Format::Directory => Box::new(directory::DirectoryReader::open(source, None)?),
+    })
+}
+
+///
+/// 打开目录读取器, 遍历时跳过指定的目录, 用于输出目录位于源目录之中时跳过已有的输出
+///
+/// # Arguments
+///
+/// * `source`: 源目录或文件
+/// * `skip`: 跳过的目录, 路径需要以`source`开头
+///
+/// returns: Result<Box<dyn ArchiveReader>, Error>
+///
+pub fn directory_reader(source: &Path, skip: &Path) -> anyhow::Result<Box<dyn ArchiveReader>> {
+    Ok(Box::new(directory::DirectoryReader::open(
+        source,
+        Some(skip),
+    )?))
+}
+
+///
+/// 解压开头的内容检查是否为tar压缩包, 单独压缩的文件(例如`access.log.gz`)与tar压缩包的魔数相同
+///
+/// # Arguments
+///
+/// * `format`: 根据魔数识别的格式, 不是压缩的tar时总是返回true
+/// * `source`: 源文件
+///
+/// returns: Result<bool, Error>
+///
+pub fn is_tarball(format: Format, source: &Path) -> anyhow::Result<bool> {
+    // 只解压开头的一个数据块, 不需要取消
+    let cancel = &CancelToken::default();
+    let block = match format {
+        Format::TarGz => tar::TarReader::open_gz(source, cancel)?.first_block()?,
+        #[cfg(feature = "bz2")]
+        Format::TarBz2 => tar::TarReader::open_bz2(source, cancel)?.first_block()?,
+        #[cfg(feature = "xz")]
+        Format::TarXz => tar::TarReader::open_xz(source, cancel)?.first_block()?,
+        #[cfg(feature = "zst")]
+        Format::TarZst => tar::TarReader::open_zst(source, cancel)?.first_block()?,
+        _ => return Ok(true),
+    };
+    Ok(tar::is_header(&block))
+}
+
+///
+/// 创建压缩包写入器
+///
+/// # Arguments
+///
+/// * `target`: 目标格式及压缩参数
+/// * `output`: 目标文件, 格式为目录时为解压目录, 均不能已存在
+/// * `password`: 加密输出的密码, 格式不支持加密时返回错误
+///
+/// returns: Result<Box<dyn ArchiveWriter>, Error>
+///
+pub fn writer(
+    target: &Target,
+    output: &Path,
+    password: Option<&Password>,
+) -> anyhow::Result<Box<dyn ArchiveWriter>> {
+    target.validate()?;
+    if password.is_some() && !target.format.encryptable() {
+        bail!("{}不支持加密, 只有zip和7z可以设置密码", target.format);
+    }
+    // 固实压缩会把未加密的条目内容暂存到磁盘, 异常退出时可能残留明文
+    #[cfg(feature = "7z")]
+    if password.is_some() && target.solid && target.format == Format::SevenZ {
+        bail!("7z固实压缩不能与加密同时使用");
+    }
+    let level = target.level.value();
+    let file = || File::create_new(output);
+    Ok(match target.format {
+        Format::Zip => Box::new(zip::ZipWriter::new(
+            file()?,
+            target.method,
+            level,
+            password.cloned(),
+        )),
+        Format::TarGz => Box::new(tar::TarWriter::new_gz(file()?, level)),
+        #[cfg(feature = "bz2")]
+        Format::TarBz2 => Box::new(tar::TarWriter::new_bz2(file()?, level)),
+        #[cfg(feature = "xz")]
+        Format::TarXz => Box::new(tar::TarWriter::new_xz(file()?, level)),
+        #[cfg(feature = "zst")]
+        Format::TarZst => Box::new(tar::TarWriter::new_zst(file()?, level)?),
+        #[cfg(feature = "7z")]
+        Format::SevenZ => Box::new(seven_z::SevenZWriter::new(
+            file()?,
+            level,
+            password,
+            target.solid.then_some(output),
+        )?),
+        Format::Directory => Box::new(directory::DirectoryWriter::create(output)?),
+    })
+}
+
+///
+/// 规范化条目路径, 去掉开头的`./`和`/`以及空的和`.`部分, 各读取器读出的路径都经过该处理
+///
+/// `tar czf x.tar.gz -C dir .`打包的条目都以`./`开头, 其中还有一个路径为`./`的根目录条目;
+/// 不处理时写入zip会变成绝对路径`/`, 再转回tar时会因为路径为空而失败
+///
+/// # Arguments
+///
+/// * `path`: 使用`/`分隔的原始路径
+///
+/// returns: Option<String> 规范化后为空时返回None, 该条目应被忽略
+///
+pub(crate) fn normalize_path(path: &str) -> Option<String> {
+    let path = path
+        .split('/')
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect::<Vec<_>>()
+        .join("/");
+    (!path.is_empty()).then_some(path)
+}
+
+///
+/// unix时间戳(秒)转为系统时间, 超出系统时间范围时返回None
+///
+/// tar头部可以使用GNU的base-256编码存储任意大的时间戳, 不能直接相加
+///
+fn from_unix_seconds(seconds: u64) -> Option<SystemTime> {
+    SystemTime::UNIX_EPOCH.checked_add(std::time::Duration::from_secs(seconds))
+}
+
+///
+/// 系统时间转为unix时间戳(秒), 早于1970年的时间按0处理
+///
+fn to_unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use std::time::Duration;
+
+    /// 条目及其内容
+    type Item = (Entry, Vec<u8>);
+
+    fn item(kind: EntryKind, path: &str, mode: u32, content: &str, link: Option<&str>) -> Item {
+        let entry = Entry {
+            path: path.to_string(),
+            kind,
+            size: content.len() as u64,
+            mode: Some(mode),
+            // 各格式都只保存到秒
+            modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            link: link.map(str::to_string),
+            ..Default::default()
+        };
+        (entry, content.as_bytes().to_vec())
+    }
+
+    ///
+    /// 包含目录、不同权限的文件和符号链接的条目, 目标格式支持时再加上硬链接
+    ///
+    fn fixture(hardlink: bool) -> Vec<Item> {
+        let mut items = vec![
+            item(EntryKind::Directory, "dir", 0o750, "", None),
+            item(EntryKind::File, "dir/a.txt", 0o640, "hello", None),
+            item(
+                EntryKind::File,
+                "run.sh",
+                0o755,
+                "#!/bin/sh\necho 中文\n",
+                None,
+            ),
+            item(EntryKind::File, "empty", 0o600, "", None),
+            item(EntryKind::Symlink, "dir/link", 0o777, "", Some("a.txt")),
+        ];
+        if hardlink {
+            items.push(item(
+                EntryKind::Hardlink,
+                "hard",
+                0o640,
+                "",
+                Some("dir/a.txt"),
+            ));
+        }
+        items
+    }
+
+    fn write_items(format: Format, output: &Path, items: &[Item]) {
+        let mut writer = writer(&Target::new(format), output, None).unwrap();
+        for (entry, content) in items {
+            writer.write(entry, &mut content.as_slice()).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    fn read_items(format: Format, source: &Path) -> Vec<Item> {
+        let mut items = vec![];
+        reader(
+            format,
+            source,
+            NameEncoding::Auto,
+            &[],
+            &CancelToken::default(),
+        )
+        .unwrap()
+        .for_each(&mut |entry, data| {
+            let mut content = vec![];
+            data.read_to_end(&mut content)?;
+            items.push((entry, content));
+            Ok(())
+        })
+        .unwrap();
+        items
+    }
+
+    ///
+    /// 由读取器直接写入另一种格式, 与转换时的流程相同
+    ///
+    fn transcode(from: Format, source: &Path, to: Format, output: &Path) {
+        let mut writer = writer(&Target::new(to), output, None).unwrap();
+        reader(
+            from,
+            source,
+            NameEncoding::Auto,
+            &[],
+            &CancelToken::default(),
+        )
+        .unwrap()
+        .for_each(&mut |entry, data| writer.write(&entry, data))
+        .unwrap();
+        writer.finish().unwrap();
+    }
+
+    ///
+    /// 比较路径、类型、内容、权限、修改时间和链接目标
+    ///
+    fn assert_items(actual: &[Item], expected: &[Item]) {
+        let summary = |items: &[Item]| {
+            items
+                .iter()
+                .map(|(entry, content)| {
+                    (
+                        entry.path.clone(),
+                        entry.kind,
+                        entry.size,
+                        content.clone(),
+                        entry.mode,
+                        entry.modified,
+                        entry.link.clone(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(summary(actual), summary(expected));
+    }
+
+    fn tar_formats() -> Vec<Format> {
+        vec![
+            Format::TarGz,
+            #[cfg(feature = "bz2")]
+            Format::TarBz2,
+            #[cfg(feature = "xz")]
+            Format::TarXz,
+            #[cfg(feature = "zst")]
+            Format::TarZst,
+        ]
+    }
+
+    #[test]
+    fn tar_round_trip() {
+        let temp = TempDir::new();
+        let items = fixture(true);
+        let source = temp.path().join("source.tar.gz");
+        write_items(Format::TarGz, &source, &items);
+        assert_items(&read_items(Format::TarGz, &source), &items);
+        for format in tar_formats() {
+            let output = temp.path().join(format!("output.{}", format.extension()));
+            transcode(Format::TarGz, &source, format, &output);
+            assert_items(&read_items(format, &output), &items);
+            let back = temp
+                .path()
+                .join(format!("back.{}.tar.gz", format.extension()));
+            transcode(format, &output, Format::TarGz, &back);
+            assert_items(&read_items(Format::TarGz, &back), &items);
+        }
+    }
+
+    #[test]
+    fn zip_tar_round_trip() {
+        let temp = TempDir::new();
+        // zip不能保存硬链接, 转换时由上层按策略处理
+        let items = fixture(false);
+        let source = temp.path().join("source.zip");
+        write_items(Format::Zip, &source, &items);
+        assert_items(&read_items(Format::Zip, &source), &items);
+        for format in tar_formats() {
+            let output = temp.path().join(format!("output.{}", format.extension()));
+            transcode(Format::Zip, &source, format, &output);
+            assert_items(&read_items(format, &output), &items);
+            let back = temp.path().join(format!("back.{}.zip", format.extension()));
+            transcode(format, &output, Format::Zip, &back);
+            assert_items(&read_items(Format::Zip, &back), &items);
+        }
+    }
+
+    #[cfg(feature = "7z")]
+    #[test]
+    fn solid_7z_rejects_password() {
+        let temp = TempDir::new();
+        let output = temp.path().join("out.7z");
+        let target = Target {
+            solid: true,
+            ..Target::new(Format::SevenZ)
+        };
+        assert!(writer(&target, &output, Some(&Password::from("secret"))).is_err());
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn normalize_entry_path() {
+        assert_eq!(normalize_path("a/b").as_deref(), Some("a/b"));
+        assert_eq!(normalize_path("./a/b/").as_deref(), Some("a/b"));
+        assert_eq!(normalize_path("/a//./b").as_deref(), Some("a/b"));
+        assert_eq!(normalize_path("../a").as_deref(), Some("../a"));
+        assert_eq!(normalize_path("./"), None);
+        assert_eq!(normalize_path("/"), None);
+        assert_eq!(normalize_path(""), None);
+    }
+
+    #[test]
+    fn unix_seconds_out_of_range() {
+        assert_eq!(from_unix_seconds(0), Some(SystemTime::UNIX_EPOCH));
+        assert_eq!(from_unix_seconds(u64::MAX), None);
+    }
+}
@@ -0,0 +1,453 @@
+use crate::cancel::{CancelRead, CancelToken};
+use crate::codec::{
+    ArchiveReader, ArchiveWriter, Attribute, Entry, EntryKind, TEMP_EXTENSION, normalize_path,
+};
+use crate::password::{Password, PasswordError};
+use anyhow::anyhow;
+use sevenz_rust2::encoder_options::{AesEncoderOptions, Lzma2Options};
+use sevenz_rust2::{ArchiveEntry, SourceReader};
+use std::fs::{File, remove_file};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// windows属性中的目录标记
+const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+
+/// p7zip的约定: 设置该标记后, 属性的高16位保存unix权限位
+const FILE_ATTRIBUTE_UNIX_EXTENSION: u32 = 0x8000;
+
+/// unix文件类型中的符号链接, 链接目标作为文件内容保存
+const S_IFLNK: u32 = 0o120000;
+
+/// unix文件类型的掩码
+const S_IFMT: u32 = 0o170000;
+
+/// LZMA2的默认压缩级别
+const DEFAULT_LEVEL: u32 = 6;
+
+pub struct SevenZReader {
+    seven_z: sevenz_rust2::ArchiveReader<BufReader<CancelRead<File>>>,
+}
+
+impl SevenZReader {
+    ///
+    /// 打开7z压缩包
+    ///
+    /// 7z不保存用于校验密码的数据, 提供了密码时依次尝试解码第一个非空条目,
+    /// 能正确解码的即为正确的密码
+    ///
+    /// 错误的密码解出的数据可能像是压缩包已损坏, 因此某个密码解码失败后仍会继续尝试其余的密码,
+    /// 全部失败时才报告损坏; 文件头已加密时, 与7-Zip一样将无法解析文件头视为密码错误
+    ///
+    /// # Arguments
+    ///
+    /// * `source`: 源文件
+    /// * `passwords`: 依次尝试的密码, 都无法解密时返回[`PasswordError`]
+    /// * `cancel`: 取消标记, 取消后试解码和读取条目都会返回错误
+    ///
+    /// returns: Result<SevenZReader, Error>
+    ///
+    pub fn open(
+        source: &Path,
+        passwords: &[Password],
+        cancel: &CancelToken,
+    ) -> anyhow::Result<Self> {
+        // 先不使用密码打开, 文件头未加密时损坏的压缩包在这里报错
+        let encrypted_header =
+            match Self::open_with(source, sevenz_rust2::Password::empty(), cancel) {
+                Ok(reader) if passwords.is_empty() => return Ok(reader),
+                Ok(_) => false,
+                Err(e)
+                    if !passwords.is_empty()
+                        && e.downcast_ref::<PasswordError>() == Some(&PasswordError::Required) =>
+                {
+                    true
+                }
+                Err(e) => return Err(e),
+            };
+        let mut corrupted = None;
+        for password in passwords {
+            cancel.check()?;
+            let mut reader = match Self::open_with(
+                source,
+                sevenz_rust2::Password::from(password.as_str()),
+                cancel,
+            ) {
+                Ok(reader) => reader,
+                Err(_) if encrypted_header => continue,
+                Err(e) if e.is::<PasswordError>() => continue,
+                Err(e) => {
+                    corrupted.get_or_insert(e);
+                    continue;
+                }
+            };
+            match reader.probe() {
+                Ok(_) => return Ok(reader),
+                Err(e) if e.is::<PasswordError>() => {}
+                Err(e) => {
+                    corrupted.get_or_insert(e);
+                }
+            }
+        }
+        Err(corrupted.unwrap_or_else(|| PasswordError::Wrong.into()))
+    }
+
+    fn open_with(
+        source: &Path,
+        password: sevenz_rust2::Password,
+        cancel: &CancelToken,
+    ) -> anyhow::Result<Self> {
+        let source = CancelRead::new(File::open(source)?, cancel.clone());
+        let seven_z = sevenz_rust2::ArchiveReader::new(BufReader::new(source), password)
+            .map_err(password_error)?;
+        Ok(Self { seven_z })
+    }
+
+    ///
+    /// 解码第一个非空条目, 用于确认密码
+    ///
+    fn probe(&mut self) -> anyhow::Result<()> {
+        self.seven_z
+            .for_each_entries(|entry: &ArchiveEntry, reader: &mut dyn Read| {
+                if entry.size() == 0 {
+                    return Ok(true);
+                }
+                std::io::copy(reader, &mut std::io::sink())?;
+                Ok(false)
+            })
+            .map_err(password_error)
+    }
+}
+
+///
+/// 将缺少密码和密码错误转换为[`PasswordError`], 与压缩包损坏区分开
+///
+fn password_error(error: sevenz_rust2::Error) -> anyhow::Error {
+    match error {
+        sevenz_rust2::Error::PasswordRequired => PasswordError::Required.into(),
+        sevenz_rust2::Error::MaybeBadPassword(_) => PasswordError::Wrong.into(),
+        error => anyhow!(error),
+    }
+}
+
+impl ArchiveReader for SevenZReader {
+    fn size(&mut self) -> anyhow::Result<u64> {
+        Ok(self
+            .seven_z
+            .archive()
+            .files
+            .iter()
+            .map(|entry| entry.size)
+            .sum::<u64>())
+    }
+
+    fn for_each(
+        &mut self,
+        visit: &mut dyn FnMut(Entry, &mut dyn Read) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        // 7z的回调只能返回`sevenz_rust2::Error`, 访问函数的错误先暂存起来, 中止遍历后再返回
+        let mut error = None;
+        self.seven_z
+            .for_each_entries(|entry: &ArchiveEntry, reader: &mut dyn Read| {
+                let Some(path) = normalize_path(entry.name()) else {
+                    std::io::copy(reader, &mut std::io::sink())?;
+                    return Ok(true);
+                };
+                let unix_mode = (entry.has_windows_attributes
+                    && entry.windows_attributes & FILE_ATTRIBUTE_UNIX_EXTENSION != 0)
+                    .then_some(entry.windows_attributes >> 16);
+                let kind = if entry.is_directory {
+                    EntryKind::Directory
+                } else if unix_mode.is_some_and(|mode| mode & S_IFMT == S_IFLNK) {
+                    EntryKind::Symlink
+                } else {
+                    EntryKind::File
+                };
+                // 符号链接的目标以文件内容的形式保存
+                let link = match kind {
+                    EntryKind::Symlink => {
+                        let mut link = String::new();
+                        reader.read_to_string(&mut link)?;
+                        Some(link)
+                    }
+                    _ => None,
+                };
+                let entry = Entry {
+                    path,
+                    kind,
+                    size: match kind {
+                        EntryKind::File => entry.size(),
+                        _ => 0,
+                    },
+                    crc32: (kind == EntryKind::File && entry.has_crc).then_some(entry.crc as u32),
+                    mode: unix_mode.map(|mode| mode & 0o7777),
+                    modified: entry
+                        .has_last_modified_date
+                        .then(|| SystemTime::from(entry.last_modified_date)),
+                    accessed: entry
+                        .has_access_date
+                        .then(|| SystemTime::from(entry.access_date)),
+                    link,
+                    ..Default::default()
+                };
+                match visit(entry, reader) {
+                    Ok(_) => Ok(true),
+                    Err(e) => {
+                        error = Some(e);
+                        Ok(false)
+                    }
+                }
+            })
+            .map_err(password_error)?;
+        match error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+pub struct SevenZWriter {
+    seven_z: sevenz_rust2::ArchiveWriter<BufWriter<File>>,
+    /// 固实压缩时暂存条目内容的文件
+    solid: Option<Spool>,
+}
+
+///
+/// 固实压缩需要一次性写入所有条目, 条目内容先依次暂存到文件中, 结束时再作为一个整体压缩
+///
+struct Spool {
+    path: PathBuf,
+    file: File,
+    entries: Vec<(ArchiveEntry, u64)>,
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        let _ = remove_file(&self.path);
+    }
+}
+
+impl SevenZWriter {
+    ///
+    /// 创建7z写入器
+    ///
+    /// # Arguments
+    ///
+    /// * `target`: 目标文件
+    /// * `level`: LZMA2的压缩级别, 为空时使用默认级别
+    /// * `password`: 加密的密码, 先压缩再使用AES-256加密, 同时加密文件头, 不输入密码无法查看文件列表
+    /// * `solid`: 固实压缩时为输出文件的路径, 条目内容未加密地暂存在其旁边的隐藏文件中, 因此不能与`password`同时指定
+    ///
+    /// returns: Result<SevenZWriter, Error>
+    ///
+    pub fn new(
+        target: File,
+        level: Option<u32>,
+        password: Option<&Password>,
+        solid: Option<&Path>,
+    ) -> anyhow::Result<Self> {
+        let mut seven_z = sevenz_rust2::ArchiveWriter::new(BufWriter::new(target))?;
+        match (password, level) {
+            (Some(password), level) => {
+                seven_z.set_content_methods(vec![
+                    AesEncoderOptions::new(sevenz_rust2::Password::from(password.as_str())).into(),
+                    Lzma2Options::from_level(level.unwrap_or(DEFAULT_LEVEL)).into(),
+                ]);
+                seven_z.set_encrypt_header(true);
+            }
+            (None, Some(level)) => {
+                seven_z.set_content_methods(vec![Lzma2Options::from_level(level).into()]);
+            }
+            (None, None) => {}
+        }
+        let solid = match solid {
+            Some(output) => {
+                // 暂存文件同样以临时文件的扩展名结尾, 打包输出目录所在的目录时会被跳过
+                let path = output.with_extension(format!("solid.{}", TEMP_EXTENSION));
+                let file = File::options()
+                    .read(true)
+                    .write(true)
+                    .create_new(true)
+                    .open(&path)?;
+                Some(Spool {
+                    path,
+                    file,
+                    entries: vec![],
+                })
+            }
+            None => None,
+        };
+        Ok(Self { seven_z, solid })
+    }
+}
+
+impl ArchiveWriter for SevenZWriter {
+    fn attributes(&self) -> &'static [Attribute] {
+        &[Attribute::Mode, Attribute::Modified, Attribute::Accessed]
+    }
+
+    fn kinds(&self) -> &'static [EntryKind] {
+        &[EntryKind::File, EntryKind::Directory, EntryKind::Symlink]
+    }
+
+    fn write(&mut self, entry: &Entry, data: &mut dyn Read) -> anyhow::Result<()> {
+        let mut archive_entry = match entry.kind {
+            EntryKind::Directory => ArchiveEntry::new_directory(&entry.path),
+            _ => ArchiveEntry::new_file(&entry.path),
+        };
+        archive_entry.has_windows_attributes = true;
+        archive_entry.windows_attributes =
+            FILE_ATTRIBUTE_UNIX_EXTENSION | (entry.mode_or_default() << 16);
+        match entry.kind {
+            EntryKind::Directory => archive_entry.windows_attributes |= FILE_ATTRIBUTE_DIRECTORY,
+            EntryKind::Symlink => archive_entry.windows_attributes |= S_IFLNK << 16,
+            _ => {}
+        }
+        if let Some(modified) = entry.modified {
+            archive_entry.has_last_modified_date = true;
+            archive_entry.last_modified_date = modified.into();
+        }
+        if let Some(accessed) = entry.accessed {
+            archive_entry.has_access_date = true;
+            archive_entry.access_date = accessed.into();
+        }
+        match entry.kind {
+            EntryKind::Directory => {
+                self.seven_z
+                    .push_archive_entry::<File>(archive_entry, None)?;
+            }
+            EntryKind::Symlink => {
+                let link = entry
+                    .link
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("链接缺少目标路径: {}", entry.path))?;
+                self.seven_z
+                    .push_archive_entry(archive_entry, Some(link.as_bytes()))?;
+            }
+            _ => match &mut self.solid {
+                Some(spool) => {
+                    let size = std::io::copy(data, &mut spool.file)?;
+                    spool.entries.push((archive_entry, size));
+                }
+                None => {
+                    self.seven_z.push_archive_entry(archive_entry, Some(data))?;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> anyhow::Result<()> {
+        if let Some(mut spool) = self.solid.take().filter(|spool| !spool.entries.is_empty()) {
+            // 按写入顺序依次读取暂存的内容, 各条目的读取器共享文件的读取位置
+            spool.file.seek(SeekFrom::Start(0))?;
+            let (entries, sizes): (Vec<_>, Vec<_>) = spool.entries.drain(..).unzip();
+            let readers = sizes
+                .into_iter()
+                .map(|size| SourceReader::new((&spool.file).take(size)))
+                .collect::<Vec<_>>();
+            self.seven_z.push_archive_entries(entries, readers)?;
+        }
+        self.seven_z.finish()?.into_inner()?.sync_all()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    /// 加密条目的内容
+    const SECRET: &str = "top secret content";
+
+    ///
+    /// 写入只有一个条目的7z, 内容和文件头都经过加密
+    ///
+    fn encrypted_7z(path: &Path, password: &str) {
+        let mut writer = Box::new(
+            SevenZWriter::new(
+                File::create_new(path).unwrap(),
+                None,
+                Some(&Password::from(password)),
+                None,
+            )
+            .unwrap(),
+        );
+        let entry = Entry {
+            path: "secret.txt".to_string(),
+            kind: EntryKind::File,
+            size: SECRET.len() as u64,
+            ..Default::default()
+        };
+        writer.write(&entry, &mut SECRET.as_bytes()).unwrap();
+        writer.finish().unwrap();
+    }
+
+    fn open_error(path: &Path, passwords: &[&str]) -> Option<PasswordError> {
+        let passwords = passwords
+            .iter()
+            .copied()
+            .map(Password::from)
+            .collect::<Vec<_>>();
+        SevenZReader::open(path, &passwords, &CancelToken::default())
+            .err()
+            .and_then(|e| e.downcast_ref::<PasswordError>().copied())
+    }
+
+    #[test]
+    fn encrypted_passwords() {
+        let temp = TempDir::new();
+        let path = temp.path().join("secret.7z");
+        encrypted_7z(&path, "right");
+        assert_eq!(open_error(&path, &[]), Some(PasswordError::Required));
+        assert_eq!(open_error(&path, &["wrong"]), Some(PasswordError::Wrong));
+        // 错误的密码之后继续尝试其余的密码
+        assert!(
+            SevenZReader::open(
+                &path,
+                &["wrong".into(), "right".into()],
+                &CancelToken::default(),
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn corrupted_is_not_password_error() {
+        let temp = TempDir::new();
+        let path = temp.path().join("secret.7z");
+        encrypted_7z(&path, "right");
+        // 截断后连签名头之后的文件头都无法找到, 与密码无关
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+        let error = SevenZReader::open(&path, &["right".into()], &CancelToken::default())
+            .err()
+            .unwrap();
+        assert!(!error.is::<PasswordError>());
+    }
+
+    #[test]
+    fn encrypted_round_trip() {
+        let temp = TempDir::new();
+        let path = temp.path().join("secret.7z");
+        encrypted_7z(&path, "right");
+        // 文件头同样加密, 文件名也不会以明文出现
+        let bytes = std::fs::read(&path).unwrap();
+        for plain in [SECRET.as_bytes(), b"secret.txt"] {
+            assert!(!bytes.windows(plain.len()).any(|window| window == plain));
+        }
+        let mut contents = vec![];
+        SevenZReader::open(&path, &["right".into()], &CancelToken::default())
+            .unwrap()
+            .for_each(&mut |entry, data| {
+                let mut content = String::new();
+                data.read_to_string(&mut content)?;
+                contents.push((entry.path, content));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(contents, [("secret.txt".to_string(), SECRET.to_string())]);
+    }
+}
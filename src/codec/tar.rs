@@ -0,0 +1,529 @@
+use crate::cancel::{CancelRead, CancelToken};
+use crate::codec::{
+    ArchiveReader, ArchiveWriter, Attribute, Entry, EntryKind, from_unix_seconds, normalize_path,
+    to_unix_seconds,
+};
+use anyhow::{Context, anyhow, bail};
+use flate2::Compression;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use tar::{Archive, Builder, EntryType, Header};
+
+/// 压缩的tar数据流, 取消后读取时返回错误
+type Source = BufReader<CancelRead<File>>;
+
+///
+/// tar压缩包读取器, `R`为解压后的tar数据流
+///
+pub struct TarReader<R: Read> {
+    path: PathBuf,
+    decoder: fn(Source) -> std::io::Result<R>,
+    cancel: CancelToken,
+}
+
+impl TarReader<flate2::read::GzDecoder<Source>> {
+    pub fn open_gz(source: &Path, cancel: &CancelToken) -> anyhow::Result<Self> {
+        Self::open(source, cancel, |source| {
+            Ok(flate2::read::GzDecoder::new(source))
+        })
+    }
+}
+
+#[cfg(feature = "bz2")]
+impl TarReader<bzip2::read::MultiBzDecoder<Source>> {
+    pub fn open_bz2(source: &Path, cancel: &CancelToken) -> anyhow::Result<Self> {
+        Self::open(source, cancel, |source| {
+            Ok(bzip2::read::MultiBzDecoder::new(source))
+        })
+    }
+}
+
+#[cfg(feature = "xz")]
+impl TarReader<xz2::read::XzDecoder<Source>> {
+    pub fn open_xz(source: &Path, cancel: &CancelToken) -> anyhow::Result<Self> {
+        Self::open(source, cancel, |source| {
+            Ok(xz2::read::XzDecoder::new_multi_decoder(source))
+        })
+    }
+}
+
+#[cfg(feature = "zst")]
+impl TarReader<zstd::stream::read::Decoder<'static, Source>> {
+    pub fn open_zst(source: &Path, cancel: &CancelToken) -> anyhow::Result<Self> {
+        Self::open(source, cancel, zstd::stream::read::Decoder::with_buffer)
+    }
+}
+
+impl<R: Read> TarReader<R> {
+    fn open(
+        source: &Path,
+        cancel: &CancelToken,
+        decoder: fn(Source) -> std::io::Result<R>,
+    ) -> anyhow::Result<Self> {
+        // 提前检查文件是否可读
+        File::open(source)?;
+        Ok(Self {
+            path: source.to_path_buf(),
+            decoder,
+            cancel: cancel.clone(),
+        })
+    }
+
+    fn source(&self) -> anyhow::Result<Source> {
+        let file = File::open(&self.path)?;
+        Ok(BufReader::new(CancelRead::new(file, self.cancel.clone())))
+    }
+
+    ///
+    /// 解压开头的512字节, 即第一个tar头部所在的数据块, 内容不足时返回实际读到的字节
+    ///
+    pub fn first_block(&self) -> anyhow::Result<Vec<u8>> {
+        let source = self.source()?;
+        let mut block = Vec::with_capacity(512);
+        (self.decoder)(source)?.take(512).read_to_end(&mut block)?;
+        Ok(block)
+    }
+
+    fn archive(&self) -> anyhow::Result<Archive<R>> {
+        let source = self.source()?;
+        Ok(Archive::new((self.decoder)(source)?))
+    }
+}
+
+impl<R: Read> ArchiveReader for TarReader<R> {
+    fn size(&mut self) -> anyhow::Result<u64> {
+        // tar没有索引, 需要先完整遍历一次才能得到解压后的大小
+        let mut archive = self.archive()?;
+        let entries = archive.entries()?;
+        let size = entries
+            .into_iter()
+            .flatten()
+            .filter(|entry| is_file(entry.header().entry_type()))
+            .flat_map(|entry| entry.header().size())
+            .sum();
+        // 取消后读取出错, 遍历提前结束, 得到的大小不完整
+        self.cancel.check()?;
+        Ok(size)
+    }
+
+    fn for_each(
+        &mut self,
+        visit: &mut dyn FnMut(Entry, &mut dyn Read) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let mut archive = self.archive()?;
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let header = entry.header();
+            let kind = match header.entry_type() {
+                entry_type if is_file(entry_type) => EntryKind::File,
+                EntryType::Directory => EntryKind::Directory,
+                EntryType::Symlink => EntryKind::Symlink,
+                EntryType::Link => EntryKind::Hardlink,
+                EntryType::Fifo => EntryKind::Fifo,
+                EntryType::Char => EntryKind::CharDevice,
+                EntryType::Block => EntryKind::BlockDevice,
+                // 扩展头部只携带其它条目的元数据, 不是实际的条目, 例如`git archive`生成的pax全局头部
+                EntryType::XGlobalHeader
+                | EntryType::XHeader
+                | EntryType::GNULongName
+                | EntryType::GNULongLink => continue,
+                entry_type => EntryKind::Unknown(entry_type.as_byte()),
+            };
+            let Some(path) = normalize_path(&entry.path()?.to_string_lossy().replace('\\', "/"))
+            else {
+                continue;
+            };
+            // 硬链接的目标是压缩包中的条目, 与条目路径一样规范化; 符号链接的目标保持原样
+            let link = entry
+                .link_name()?
+                .map(|link| link.to_string_lossy().replace('\\', "/"))
+                .map(|link| match kind {
+                    EntryKind::Hardlink => normalize_path(&link).unwrap_or(link),
+                    _ => link,
+                });
+            // 只有设备文件的设备号字段有意义, 其它条目可能为空
+            let device = match kind {
+                EntryKind::CharDevice | EntryKind::BlockDevice => {
+                    match (header.device_major()?, header.device_minor()?) {
+                        (Some(major), Some(minor)) => Some((major, minor)),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            let entry_info = Entry {
+                path,
+                kind,
+                size: match kind {
+                    EntryKind::File => entry.size(),
+                    _ => 0,
+                },
+                mode: header.mode().ok().map(|mode| mode & 0o7777),
+                modified: header.mtime().ok().and_then(from_unix_seconds),
+                // 只有GNU格式的头部才有访问时间, 0表示未设置
+                accessed: header
+                    .as_gnu()
+                    .and_then(|gnu| gnu.atime().ok())
+                    .filter(|atime| *atime > 0)
+                    .and_then(from_unix_seconds),
+                uid: header.uid().ok(),
+                gid: header.gid().ok(),
+                user: header
+                    .username()
+                    .ok()
+                    .flatten()
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string),
+                group: header
+                    .groupname()
+                    .ok()
+                    .flatten()
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string),
+                link,
+                device,
+                ..Default::default()
+            };
+            visit(entry_info, &mut entry)?;
+        }
+        // tar在结束标记处停止读取, 需要读完剩余的压缩流才会校验尾部(例如gzip的CRC32)
+        std::io::copy(&mut archive.into_inner(), &mut std::io::sink())
+            .context("压缩流末尾已损坏")?;
+        Ok(())
+    }
+}
+
+///
+/// 判断数据块是否为tar头部: 带有ustar标记, 或者校验和正确(旧的v7格式没有标记);
+/// 全为0的数据块是结束标记, 视为空的tar
+///
+pub(crate) fn is_header(block: &[u8]) -> bool {
+    let Some(block) = block.get(..512) else {
+        return false;
+    };
+    if block.iter().all(|byte| *byte == 0) || &block[257..262] == b"ustar" {
+        return true;
+    }
+    // 校验和为八进制数字, 以空格或NUL结尾; 计算时校验和字段本身按8个空格计算
+    let expected = std::str::from_utf8(&block[148..156])
+        .ok()
+        .map(|checksum| checksum.trim_matches(|c| c == ' ' || c == '\0'))
+        .and_then(|checksum| u32::from_str_radix(checksum, 8).ok());
+    let actual = block
+        .iter()
+        .enumerate()
+        .map(|(index, byte)| match index {
+            148..156 => b' ' as u32,
+            _ => *byte as u32,
+        })
+        .sum::<u32>();
+    expected == Some(actual)
+}
+
+///
+/// 是否为带有数据的普通文件, 稀疏文件读取时会展开为完整的内容
+///
+fn is_file(entry_type: EntryType) -> bool {
+    matches!(
+        entry_type,
+        EntryType::Regular | EntryType::Continuous | EntryType::GNUSparse
+    )
+}
+
+///
+/// tar压缩包写入器, `W`为压缩后的输出流
+///
+pub struct TarWriter<W: Write> {
+    builder: Builder<W>,
+    finish: fn(W) -> std::io::Result<BufWriter<File>>,
+}
+
+impl TarWriter<flate2::write::GzEncoder<BufWriter<File>>> {
+    pub fn new_gz(target: File, level: Option<u32>) -> Self {
+        Self {
+            builder: Builder::new(flate2::write::GzEncoder::new(
+                BufWriter::new(target),
+                level.map(Compression::new).unwrap_or_default(),
+            )),
+            finish: flate2::write::GzEncoder::finish,
+        }
+    }
+}
+
+#[cfg(feature = "bz2")]
+impl TarWriter<bzip2::write::BzEncoder<BufWriter<File>>> {
+    pub fn new_bz2(target: File, level: Option<u32>) -> Self {
+        Self {
+            builder: Builder::new(bzip2::write::BzEncoder::new(
+                BufWriter::new(target),
+                level.map(bzip2::Compression::new).unwrap_or_default(),
+            )),
+            finish: bzip2::write::BzEncoder::finish,
+        }
+    }
+}
+
+#[cfg(feature = "xz")]
+impl TarWriter<xz2::write::XzEncoder<BufWriter<File>>> {
+    pub fn new_xz(target: File, level: Option<u32>) -> Self {
+        Self {
+            builder: Builder::new(xz2::write::XzEncoder::new(
+                BufWriter::new(target),
+                level.unwrap_or(6),
+            )),
+            finish: xz2::write::XzEncoder::finish,
+        }
+    }
+}
+
+#[cfg(feature = "zst")]
+impl TarWriter<zstd::stream::write::Encoder<'static, BufWriter<File>>> {
+    pub fn new_zst(target: File, level: Option<u32>) -> anyhow::Result<Self> {
+        Ok(Self {
+            builder: Builder::new(zstd::stream::write::Encoder::new(
+                BufWriter::new(target),
+                level.map_or(zstd::DEFAULT_COMPRESSION_LEVEL, |level| level as i32),
+            )?),
+            finish: zstd::stream::write::Encoder::finish,
+        })
+    }
+}
+
+///
+/// 用户名或组名能否写入头部, 字段长度为32字节且不能包含NUL
+///
+fn fits_name(name: &str) -> bool {
+    name.len() <= 32 && !name.contains('\0')
+}
+
+impl<W: Write> ArchiveWriter for TarWriter<W> {
+    fn attributes(&self) -> &'static [Attribute] {
+        &[
+            Attribute::Mode,
+            Attribute::Modified,
+            Attribute::Accessed,
+            Attribute::Owner,
+            Attribute::OwnerName,
+        ]
+    }
+
+    fn kinds(&self) -> &'static [EntryKind] {
+        &[
+            EntryKind::File,
+            EntryKind::Directory,
+            EntryKind::Symlink,
+            EntryKind::Hardlink,
+            EntryKind::Fifo,
+            EntryKind::CharDevice,
+            EntryKind::BlockDevice,
+        ]
+    }
+
+    fn dropped(&self, entry: &Entry) -> Vec<Attribute> {
+        // 其它属性都能保留, 只有放不下的用户名或组名会丢失
+        [&entry.user, &entry.group]
+            .into_iter()
+            .flatten()
+            .any(|name| !fits_name(name))
+            .then_some(Attribute::OwnerName)
+            .into_iter()
+            .collect()
+    }
+
+    fn write(&mut self, entry: &Entry, data: &mut dyn Read) -> anyhow::Result<()> {
+        let mut header = Header::new_gnu();
+        header.set_mode(entry.mode_or_default());
+        if let Some(modified) = entry.modified {
+            header.set_mtime(to_unix_seconds(modified));
+        }
+        if let (Some(accessed), Some(gnu)) = (entry.accessed, header.as_gnu_mut()) {
+            gnu.set_atime(to_unix_seconds(accessed));
+        }
+        if let Some(uid) = entry.uid {
+            header.set_uid(uid);
+        }
+        if let Some(gid) = entry.gid {
+            header.set_gid(gid);
+        }
+        // 放不下的名称不写入, 由`dropped`报告
+        if let Some(user) = entry.user.as_deref().filter(|name| fits_name(name)) {
+            header.set_username(user)?;
+        }
+        if let Some(group) = entry.group.as_deref().filter(|name| fits_name(name)) {
+            header.set_groupname(group)?;
+        }
+        header.set_size(0);
+        match entry.kind {
+            EntryKind::File => {
+                header.set_size(entry.size);
+                header.set_entry_type(EntryType::Regular);
+                self.builder.append_data(&mut header, &entry.path, data)?;
+            }
+            EntryKind::Directory => {
+                header.set_entry_type(EntryType::Directory);
+                self.builder
+                    .append_data(&mut header, &entry.path, std::io::empty())?;
+            }
+            EntryKind::Symlink | EntryKind::Hardlink => {
+                header.set_entry_type(match entry.kind {
+                    EntryKind::Symlink => EntryType::Symlink,
+                    _ => EntryType::Link,
+                });
+                let link = entry
+                    .link
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("链接缺少目标路径: {}", entry.path))?;
+                self.builder.append_link(&mut header, &entry.path, link)?;
+            }
+            EntryKind::Fifo | EntryKind::CharDevice | EntryKind::BlockDevice => {
+                header.set_entry_type(match entry.kind {
+                    EntryKind::Fifo => EntryType::Fifo,
+                    EntryKind::CharDevice => EntryType::Char,
+                    _ => EntryType::Block,
+                });
+                if let Some((major, minor)) = entry.device {
+                    header.set_device_major(major)?;
+                    header.set_device_minor(minor)?;
+                }
+                self.builder
+                    .append_data(&mut header, &entry.path, std::io::empty())?;
+            }
+            EntryKind::Unknown(_) => bail!("无法写入{}: {}", entry.kind, entry.path),
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> anyhow::Result<()> {
+        let encoder = self.builder.into_inner()?;
+        (self.finish)(encoder)?.into_inner()?.sync_all()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn header(path: &str, header: &mut Header) -> Vec<u8> {
+        header.set_path(path).unwrap();
+        header.set_size(0);
+        header.set_cksum();
+        header.as_bytes().to_vec()
+    }
+
+    #[test]
+    fn tar_header() {
+        assert!(is_header(&header("a.txt", &mut Header::new_gnu())));
+        assert!(is_header(&header("a.txt", &mut Header::new_ustar())));
+        // v7格式没有ustar标记, 只能通过校验和识别
+        assert!(is_header(&header("a.txt", &mut Header::new_old())));
+        // 空的tar只有结束标记
+        assert!(is_header(&[0; 1024]));
+    }
+
+    #[test]
+    fn not_tar_header() {
+        let mut block = header("a.txt", &mut Header::new_old());
+        block[0] = b'b';
+        assert!(!is_header(&block));
+        assert!(!is_header(&[b'a'; 512]));
+        assert!(!is_header(
+            b"127.0.0.1 - - [01/Jan/2025] \"GET / HTTP/1.1\" 200"
+        ));
+        assert!(!is_header(&[]));
+    }
+
+    #[test]
+    fn base256_mtime_out_of_range() {
+        let temp = TempDir::new();
+        let source = temp.path().join("time.tar.gz");
+        let mut header = Header::new_gnu();
+        header.set_path("a.txt").unwrap();
+        header.set_size(1);
+        // GNU的base-256编码: 最高位为1, 其余为大端整数, 这里的时间戳超出系统时间的范围
+        header.as_old_mut().mtime = [
+            0x80, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        ];
+        header.set_cksum();
+        let mut builder = Builder::new(flate2::write::GzEncoder::new(
+            File::create(&source).unwrap(),
+            Compression::default(),
+        ));
+        builder.append(&header, &b"a"[..]).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let mut entries = vec![];
+        TarReader::open_gz(&source, &CancelToken::default())
+            .unwrap()
+            .for_each(&mut |entry, reader| {
+                let mut content = String::new();
+                reader.read_to_string(&mut content)?;
+                entries.push((entry, content));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0.path, "a.txt");
+        assert_eq!(entries[0].0.modified, None);
+        assert_eq!(entries[0].1, "a");
+    }
+
+    #[test]
+    fn long_owner_name() {
+        let temp = TempDir::new();
+        let source = temp.path().join("owner.tar.gz");
+        let mut writer = Box::new(TarWriter::new_gz(File::create_new(&source).unwrap(), None));
+        let entry = Entry {
+            path: "a.txt".to_string(),
+            size: 5,
+            user: Some("u".repeat(33)),
+            group: Some("staff".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(writer.dropped(&entry), [Attribute::OwnerName]);
+        writer.write(&entry, &mut &b"hello"[..]).unwrap();
+        writer.finish().unwrap();
+
+        let mut entries = vec![];
+        TarReader::open_gz(&source, &CancelToken::default())
+            .unwrap()
+            .for_each(&mut |entry, _| {
+                entries.push(entry);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].user, None);
+        assert_eq!(entries[0].group.as_deref(), Some("staff"));
+    }
+
+    #[test]
+    fn cancel_size_pass() {
+        let temp = TempDir::new();
+        let source = temp.path().join("data.tar.gz");
+        let mut writer = Box::new(TarWriter::new_gz(File::create_new(&source).unwrap(), None));
+        let entry = Entry {
+            path: "a.txt".to_string(),
+            size: 5,
+            ..Default::default()
+        };
+        writer.write(&entry, &mut &b"hello"[..]).unwrap();
+        writer.finish().unwrap();
+        let cancel = CancelToken::default();
+        assert_eq!(
+            TarReader::open_gz(&source, &cancel)
+                .unwrap()
+                .size()
+                .unwrap(),
+            5
+        );
+        cancel.cancel();
+        let error = TarReader::open_gz(&source, &cancel)
+            .unwrap()
+            .size()
+            .unwrap_err();
+        assert!(error.is::<crate::cancel::Cancelled>());
+    }
+}
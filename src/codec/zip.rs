@@ -0,0 +1,719 @@
+use crate::cancel::{CancelRead, CancelToken};
+use crate::codec::{
+    ArchiveReader, ArchiveWriter, Attribute, Entry, EntryKind, from_unix_seconds, normalize_path,
+    to_unix_seconds,
+};
+use crate::format::{NameEncoding, ZipMethod};
+use crate::password::{Password, PasswordError};
+use crate::time::{civil_from_days, days_from_civil};
+use anyhow::anyhow;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read};
+use std::path::Path;
+use std::time::SystemTime;
+use zip::extra_fields::ExtraField;
+use zip::result::ZipError;
+use zip::write::{ExtendedFileOptions, FileOptions};
+use zip::{AesMode, CompressionMethod, DateTime, HasZipMetadata, ZipArchive};
+
+/// 扩展时间戳, 以unix时间戳保存修改时间和访问时间
+const EXTENDED_TIMESTAMP: u16 = 0x5455;
+
+/// Info-ZIP的unix扩展字段, 保存uid和gid
+const INFO_ZIP_UNIX: u16 = 0x7875;
+
+/// CP437中0x80-0xFF对应的字符
+const CP437: &str = "ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜ¢£¥₧ƒáíóúñÑªº¿⌐¬½¼¡«»░▒▓│┤╡╢╖╕╣║╗╝╜╛┐└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀αßΓπΣσµτΦΘΩδ∞φε∩≡±≥≤⌠⌡÷≈°∙·√ⁿ²■\u{a0}";
+
+/// 取消后读取时返回错误的源文件
+type Source = BufReader<CancelRead<File>>;
+
+pub struct ZipReader {
+    zip: ZipArchive<Source>,
+    /// 未设置UTF-8标记的文件名的编码, 已经过自动识别
+    encoding: NameEncoding,
+    /// 加密条目使用的密码, 没有加密条目时为空
+    password: Option<Password>,
+}
+
+impl ZipReader {
+    ///
+    /// 打开zip压缩包
+    ///
+    /// # Arguments
+    ///
+    /// * `source`: 源文件
+    /// * `encoding`: 未设置UTF-8标记的文件名的编码, 为[`NameEncoding::Auto`]时根据这些文件名识别,
+    ///   设置了标记的文件名始终按UTF-8读取
+    /// * `passwords`: 存在加密条目时依次尝试的密码, 都无法解密时返回[`PasswordError`]
+    /// * `cancel`: 取消标记, 取消后试解密和读取条目都会返回错误
+    ///
+    /// returns: Result<ZipReader, Error>
+    ///
+    pub fn open(
+        source: &Path,
+        encoding: NameEncoding,
+        passwords: &[Password],
+        cancel: &CancelToken,
+    ) -> anyhow::Result<Self> {
+        let source = CancelRead::new(File::open(source)?, cancel.clone());
+        let mut zip = ZipArchive::new(BufReader::new(source))?;
+        let encoding = match encoding {
+            NameEncoding::Auto => {
+                // 损坏的条目留到读取时再报错, 带UTF-8标记的条目不参与识别
+                let names = (0..zip.len())
+                    .filter_map(|index| {
+                        let file = zip.by_index_raw(index).ok()?;
+                        (!is_utf8(&file)).then(|| file.name_raw().to_vec())
+                    })
+                    .collect::<Vec<_>>();
+                detect_encoding(&names)
+            }
+            encoding => encoding,
+        };
+        let password = select_password(&mut zip, passwords)?;
+        Ok(Self {
+            zip,
+            encoding,
+            password,
+        })
+    }
+}
+
+///
+/// 从候选密码中选出能解密的密码
+///
+/// ZipCrypto只用一个字节校验密码, 错误的密码有1/256的概率通过校验, 因此用最小的加密条目
+/// 完整解密一次, 由CRC32确认密码正确; AES同样先校验密码再由HMAC确认
+///
+/// 所有密码都未通过校验时返回[`PasswordError`]; 通过了校验但数据无法解密时视为条目损坏
+///
+fn select_password(
+    zip: &mut ZipArchive<Source>,
+    passwords: &[Password],
+) -> anyhow::Result<Option<Password>> {
+    let smallest = (0..zip.len())
+        .filter_map(|index| {
+            let file = zip.by_index_raw(index).ok()?;
+            file.encrypted()
+                .then(|| (file.compressed_size(), index, file.name().to_string()))
+        })
+        .min();
+    let Some((_, index, name)) = smallest else {
+        return Ok(None);
+    };
+    let mut corrupted = None;
+    for password in passwords {
+        let result = match zip.by_index_decrypt(index, password.as_bytes()) {
+            Err(ZipError::InvalidPassword) => continue,
+            Err(e) => Err(anyhow!(e)),
+            Ok(mut file) => std::io::copy(&mut file, &mut std::io::sink()).map_err(|e| anyhow!(e)),
+        };
+        match result {
+            Ok(_) => return Ok(Some(password.clone())),
+            Err(e) => {
+                corrupted.get_or_insert(e.context(format!("条目已损坏: {}", name)));
+            }
+        }
+    }
+    Err(match (corrupted, passwords.is_empty()) {
+        (Some(e), _) => e,
+        (None, true) => PasswordError::Required.into(),
+        (None, false) => PasswordError::Wrong.into(),
+    })
+}
+
+///
+/// 将读取条目时的错误转换为密码错误或条目损坏
+///
+fn entry_error(error: ZipError, name: &str) -> anyhow::Error {
+    match error {
+        ZipError::InvalidPassword => PasswordError::Wrong.into(),
+        ZipError::UnsupportedArchive(message) if message == ZipError::PASSWORD_REQUIRED => {
+            PasswordError::Required.into()
+        }
+        error => anyhow!(error).context(format!("条目已损坏: {}", name)),
+    }
+}
+
+///
+/// 识别文件名的编码
+///
+/// 全部是合法的UTF-8时按UTF-8处理, 否则在GBK和Shift-JIS中选择,
+/// 两者的双字节区间大量重叠, 需要结合内容判断:
+///
+/// * GBK: 能无错解码, 且大部分双字节字符落在GB2312区间(高低字节都在0xA1-0xFE)
+/// * Shift-JIS: 能无错解码, 解码结果只包含常用的日文字符且至少有一个假名
+///
+/// 都不满足时按zip规范使用CP437, 西文名称中零散的高位字节很容易被误认为双字节字符
+///
+/// # Arguments
+///
+/// * `names`: 未设置UTF-8标记的文件名
+///
+/// returns: NameEncoding
+///
+fn detect_encoding(names: &[Vec<u8>]) -> NameEncoding {
+    let names = names
+        .iter()
+        .filter(|name| std::str::from_utf8(name).is_err())
+        .collect::<Vec<_>>();
+    if names.is_empty() {
+        return NameEncoding::Utf8;
+    }
+    let decode = |encoding: &'static encoding_rs::Encoding| {
+        names
+            .iter()
+            .map(|name| encoding.decode_without_bom_handling_and_without_replacement(name))
+            .collect::<Option<Vec<_>>>()
+    };
+    let (mut pairs, mut gb2312) = (0, 0);
+    for name in &names {
+        let mut bytes = name.iter();
+        while let Some(&lead) = bytes.next() {
+            if lead < 0x80 {
+                continue;
+            }
+            let Some(&trail) = bytes.next() else {
+                break;
+            };
+            pairs += 1;
+            if (0xA1..=0xFE).contains(&lead) && (0xA1..=0xFE).contains(&trail) {
+                gb2312 += 1;
+            }
+        }
+    }
+    if decode(encoding_rs::GBK).is_some() && gb2312 * 2 >= pairs {
+        return NameEncoding::Gbk;
+    }
+    let japanese = decode(encoding_rs::SHIFT_JIS).is_some_and(|names| {
+        let chars = || names.iter().flat_map(|name| name.chars());
+        let kana = |c: char| ('\u{3040}'..='\u{30ff}').contains(&c);
+        chars().all(|c| {
+            c.is_ascii()
+                || kana(c)
+                || ('\u{3000}'..='\u{303f}').contains(&c)
+                || ('\u{4e00}'..='\u{9fff}').contains(&c)
+                || ('\u{ff01}'..='\u{ff5e}').contains(&c)
+        }) && chars().any(kana)
+    });
+    match japanese {
+        true => NameEncoding::ShiftJis,
+        false => NameEncoding::Cp437,
+    }
+}
+
+///
+/// 条目是否设置了UTF-8标记(通用标志位第11位), 带Info-ZIP Unicode路径扩展字段的条目同样视为UTF-8
+///
+fn is_utf8(file: &impl HasZipMetadata) -> bool {
+    file.get_metadata().is_utf8
+}
+
+///
+/// 按指定编码解码文件名或链接目标, 无法解码的字节使用替换字符
+///
+fn decode_name(encoding: NameEncoding, name: &[u8]) -> String {
+    match encoding {
+        NameEncoding::Auto | NameEncoding::Utf8 => String::from_utf8_lossy(name).into_owned(),
+        NameEncoding::Gbk => encoding_rs::GBK
+            .decode_without_bom_handling(name)
+            .0
+            .into_owned(),
+        NameEncoding::ShiftJis => encoding_rs::SHIFT_JIS
+            .decode_without_bom_handling(name)
+            .0
+            .into_owned(),
+        NameEncoding::Cp437 => name
+            .iter()
+            .map(|&byte| match byte {
+                0..0x80 => byte as char,
+                _ => CP437
+                    .chars()
+                    .nth(byte as usize - 0x80)
+                    .unwrap_or('\u{fffd}'),
+            })
+            .collect(),
+    }
+}
+
+impl ArchiveReader for ZipReader {
+    fn size(&mut self) -> anyhow::Result<u64> {
+        Ok(self
+            .zip
+            .decompressed_size()
+            .map(|size| size as u64)
+            .unwrap_or(u64::MAX))
+    }
+
+    fn for_each(
+        &mut self,
+        visit: &mut dyn FnMut(Entry, &mut dyn Read) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        for index in 0..self.zip.len() {
+            let name = self
+                .zip
+                .name_for_index(index)
+                .unwrap_or_default()
+                .to_string();
+            let file = match &self.password {
+                Some(password) => self.zip.by_index_decrypt(index, password.as_bytes()),
+                None => self.zip.by_index(index),
+            };
+            let mut file = file.map_err(|e| entry_error(e, &name))?;
+            // 同一个压缩包中可能只有部分条目设置了UTF-8标记, 例如7-Zip只为无法用本地编码表示的名称设置标记
+            let encoding = match is_utf8(&file) {
+                true => NameEncoding::Utf8,
+                false => self.encoding,
+            };
+            let Some(path) = normalize_path(&decode_name(encoding, file.name_raw())) else {
+                continue;
+            };
+            let timestamp = file.extra_data_fields().find_map(|field| match field {
+                ExtraField::ExtendedTimestamp(timestamp) => Some(timestamp.clone()),
+                _ => None,
+            });
+            let (uid, gid) = file
+                .extra_data()
+                .and_then(read_unix_owner)
+                .map(|(uid, gid)| (Some(uid), Some(gid)))
+                .unwrap_or_default();
+            let kind = if file.is_dir() {
+                EntryKind::Directory
+            } else if file.is_symlink() {
+                EntryKind::Symlink
+            } else {
+                EntryKind::File
+            };
+            // 符号链接的目标以文件内容的形式保存
+            let link = match kind {
+                EntryKind::Symlink => {
+                    let mut link = vec![];
+                    file.read_to_end(&mut link)?;
+                    Some(decode_name(encoding, &link))
+                }
+                _ => None,
+            };
+            let entry = Entry {
+                path,
+                kind,
+                size: match kind {
+                    EntryKind::File => file.size(),
+                    _ => 0,
+                },
+                // AES加密的条目可以不保存CRC(AE-2), 由HMAC校验内容
+                crc32: (kind == EntryKind::File && file.get_metadata().aes_mode.is_none())
+                    .then(|| file.crc32()),
+                mode: file.unix_mode().map(|mode| mode & 0o7777),
+                modified: timestamp
+                    .as_ref()
+                    .and_then(|timestamp| timestamp.mod_time())
+                    .and_then(|seconds| from_unix_seconds(seconds as u64))
+                    .or_else(|| file.last_modified().and_then(from_dos_time)),
+                accessed: timestamp
+                    .as_ref()
+                    .and_then(|timestamp| timestamp.ac_time())
+                    .and_then(|seconds| from_unix_seconds(seconds as u64)),
+                uid,
+                gid,
+                link,
+                ..Default::default()
+            };
+            visit(entry, &mut file)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct ZipWriter {
+    zip: zip::ZipWriter<BufWriter<File>>,
+    method: CompressionMethod,
+    level: Option<i64>,
+    /// 使用AES-256加密所有条目的密码
+    password: Option<Password>,
+}
+
+impl ZipWriter {
+    pub fn new(
+        target: File,
+        method: ZipMethod,
+        level: Option<u32>,
+        password: Option<Password>,
+    ) -> Self {
+        Self {
+            zip: zip::ZipWriter::new(BufWriter::new(target)),
+            method: match method {
+                ZipMethod::Stored => CompressionMethod::Stored,
+                ZipMethod::Deflate => CompressionMethod::Deflated,
+                ZipMethod::Bzip2 => CompressionMethod::Bzip2,
+                ZipMethod::Zstd => CompressionMethod::Zstd,
+                ZipMethod::Xz => CompressionMethod::Xz,
+            },
+            level: level.map(i64::from),
+            password,
+        }
+    }
+}
+
+impl ArchiveWriter for ZipWriter {
+    fn attributes(&self) -> &'static [Attribute] {
+        &[
+            Attribute::Mode,
+            Attribute::Modified,
+            Attribute::Accessed,
+            Attribute::Owner,
+        ]
+    }
+
+    fn kinds(&self) -> &'static [EntryKind] {
+        &[EntryKind::File, EntryKind::Directory, EntryKind::Symlink]
+    }
+
+    fn write(&mut self, entry: &Entry, data: &mut dyn Read) -> anyhow::Result<()> {
+        // 文件名统一使用UTF-8, zip库会为包含非ASCII字符的文件名设置UTF-8标记
+        let mut options = FileOptions::<ExtendedFileOptions>::default()
+            .compression_method(self.method)
+            .compression_level(self.level)
+            .unix_permissions(entry.mode_or_default())
+            .large_file(entry.size >= u32::MAX as u64);
+        if let Some(password) = &self.password {
+            options = options.with_aes_encryption(AesMode::Aes256, password.as_str());
+        }
+        if let Some(modified) = entry.modified {
+            options = options.last_modified_time(to_dos_time(modified));
+            // 访问时间只能跟随修改时间一起写入扩展时间戳
+            let mut timestamp = vec![0b01];
+            timestamp.extend((to_unix_seconds(modified) as u32).to_le_bytes());
+            if let Some(accessed) = entry.accessed {
+                timestamp[0] |= 0b10;
+                timestamp.extend((to_unix_seconds(accessed) as u32).to_le_bytes());
+            }
+            options.add_extra_data(EXTENDED_TIMESTAMP, timestamp.into_boxed_slice(), false)?;
+        }
+        if entry.uid.is_some() || entry.gid.is_some() {
+            let mut owner = vec![1, 4];
+            owner.extend((entry.uid.unwrap_or(0) as u32).to_le_bytes());
+            owner.push(4);
+            owner.extend((entry.gid.unwrap_or(0) as u32).to_le_bytes());
+            options.add_extra_data(INFO_ZIP_UNIX, owner.into_boxed_slice(), false)?;
+        }
+        match entry.kind {
+            EntryKind::Directory => self.zip.add_directory_from_path(&entry.path, options)?,
+            EntryKind::Symlink => {
+                let link = entry
+                    .link
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("链接缺少目标路径: {}", entry.path))?;
+                self.zip.add_symlink(&entry.path, link, options)?
+            }
+            _ => {
+                self.zip.start_file_from_path(&entry.path, options)?;
+                std::io::copy(data, &mut self.zip)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> anyhow::Result<()> {
+        self.zip.finish()?.into_inner()?.sync_all()?;
+        Ok(())
+    }
+}
+
+///
+/// 从扩展字段中读取Info-ZIP的uid和gid
+///
+fn read_unix_owner(mut extra: &[u8]) -> Option<(u64, u64)> {
+    fn read_id(data: &[u8]) -> Option<(u64, &[u8])> {
+        let (size, data) = data.split_first()?;
+        let size = *size as usize;
+        if size > 8 || data.len() < size {
+            return None;
+        }
+        let mut bytes = [0u8; 8];
+        bytes[..size].copy_from_slice(&data[..size]);
+        Some((u64::from_le_bytes(bytes), &data[size..]))
+    }
+
+    while extra.len() >= 4 {
+        let id = u16::from_le_bytes([extra[0], extra[1]]);
+        let len = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+        let data = extra.get(4..4 + len)?;
+        if id == INFO_ZIP_UNIX && data.first() == Some(&1) {
+            let (uid, data) = read_id(&data[1..])?;
+            let (gid, _) = read_id(data)?;
+            return Some((uid, gid));
+        }
+        extra = &extra[4 + len..];
+    }
+    None
+}
+
+///
+/// DOS时间转为系统时间, DOS时间不带时区, 这里按UTC处理
+///
+fn from_dos_time(time: DateTime) -> Option<SystemTime> {
+    let days = days_from_civil(time.year() as i64, time.month() as i64, time.day() as i64);
+    let seconds =
+        days * 86400 + time.hour() as i64 * 3600 + time.minute() as i64 * 60 + time.second() as i64;
+    from_unix_seconds(seconds.max(0) as u64)
+}
+
+///
+/// 系统时间转为DOS时间, 超出DOS时间范围(1980-2107)时使用默认值
+///
+fn to_dos_time(time: SystemTime) -> DateTime {
+    let seconds = to_unix_seconds(time) as i64;
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86400));
+    let rem = seconds.rem_euclid(86400);
+    DateTime::from_date_and_time(
+        year as u16,
+        month as u8,
+        day as u8,
+        (rem / 3600) as u8,
+        (rem % 3600 / 60) as u8,
+        (rem % 60) as u8,
+    )
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn encode(encoding: &'static encoding_rs::Encoding, names: &[&str]) -> Vec<Vec<u8>> {
+        names
+            .iter()
+            .map(|name| encoding.encode(name).0.into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn detect_utf8() {
+        assert_eq!(detect_encoding(&[]), NameEncoding::Utf8);
+        let names = encode(
+            encoding_rs::UTF_8,
+            &["readme.txt", "中文/文件.txt", "テスト.txt"],
+        );
+        assert_eq!(detect_encoding(&names), NameEncoding::Utf8);
+    }
+
+    #[test]
+    fn detect_gbk() {
+        let names = encode(
+            encoding_rs::GBK,
+            &["文档/报告.docx", "图片.png", "readme.txt"],
+        );
+        assert_eq!(detect_encoding(&names), NameEncoding::Gbk);
+    }
+
+    #[test]
+    fn detect_shift_jis() {
+        let names = encode(
+            encoding_rs::SHIFT_JIS,
+            &["テスト/データ.txt", "ファイル.csv"],
+        );
+        assert_eq!(detect_encoding(&names), NameEncoding::ShiftJis);
+    }
+
+    #[test]
+    fn detect_cp437() {
+        // CP437中0x82为é, 0x94为ö
+        let names = vec![b"caf\x82.txt".to_vec(), b"sch\x94n.doc".to_vec()];
+        assert_eq!(detect_encoding(&names), NameEncoding::Cp437);
+        assert_eq!(decode_name(NameEncoding::Cp437, &names[0]), "café.txt");
+    }
+
+    #[test]
+    fn decode_with_encoding() {
+        let name = encoding_rs::GBK.encode("中文.txt").0;
+        assert_eq!(decode_name(NameEncoding::Gbk, &name), "中文.txt");
+        let name = encoding_rs::SHIFT_JIS.encode("テスト.txt").0;
+        assert_eq!(decode_name(NameEncoding::ShiftJis, &name), "テスト.txt");
+    }
+
+    ///
+    /// 手工构造只包含存储条目的zip, 可以分别指定每个条目的原始文件名和是否设置UTF-8标记
+    ///
+    fn raw_zip(path: &Path, entries: &[(Vec<u8>, bool)]) {
+        let (mut data, mut central) = (vec![], vec![]);
+        for (name, utf8) in entries {
+            let flags: u16 = if *utf8 { 1 << 11 } else { 0 };
+            let offset = data.len() as u32;
+            // 版本、标志、存储方式、时间、日期(1980-01-01)、CRC、压缩前后大小(内容为空)
+            let fields = [
+                &20u16.to_le_bytes()[..],
+                &flags.to_le_bytes(),
+                &0u16.to_le_bytes(),
+                &0u16.to_le_bytes(),
+                &0x21u16.to_le_bytes(),
+                &0u32.to_le_bytes(),
+                &0u32.to_le_bytes(),
+                &0u32.to_le_bytes(),
+                &(name.len() as u16).to_le_bytes(),
+                &0u16.to_le_bytes(),
+            ]
+            .concat();
+            data.extend_from_slice(b"PK\x03\x04");
+            data.extend_from_slice(&fields);
+            data.extend_from_slice(name);
+            central.extend_from_slice(b"PK\x01\x02");
+            central.extend_from_slice(&20u16.to_le_bytes());
+            central.extend_from_slice(&fields);
+            // 注释长度、磁盘号、内部属性、外部属性、本地头部的位置
+            central.extend_from_slice(&[0; 10]);
+            central.extend_from_slice(&offset.to_le_bytes());
+            central.extend_from_slice(name);
+        }
+        let count = (entries.len() as u16).to_le_bytes();
+        let end = [
+            &b"PK\x05\x06\0\0\0\0"[..],
+            &count,
+            &count,
+            &(central.len() as u32).to_le_bytes(),
+            &(data.len() as u32).to_le_bytes(),
+            &0u16.to_le_bytes(),
+        ]
+        .concat();
+        std::fs::write(path, [data, central, end].concat()).unwrap();
+    }
+
+    fn read_names(path: &Path, encoding: NameEncoding) -> Vec<String> {
+        let mut names = vec![];
+        ZipReader::open(path, encoding, &[], &CancelToken::default())
+            .unwrap()
+            .for_each(&mut |entry, _| {
+                names.push(entry.path);
+                Ok(())
+            })
+            .unwrap();
+        names
+    }
+
+    #[test]
+    fn mixed_utf8_flags() {
+        let temp = TempDir::new();
+        let path = temp.path().join("mixed.zip");
+        let gbk = |name: &str| encoding_rs::GBK.encode(name).0.into_owned();
+        raw_zip(
+            &path,
+            &[
+                (gbk("文档/报告.txt"), false),
+                ("日本語/テスト.txt".as_bytes().to_vec(), true),
+                (gbk("图片.png"), false),
+            ],
+        );
+        let expected = ["文档/报告.txt", "日本語/テスト.txt", "图片.png"];
+        assert_eq!(read_names(&path, NameEncoding::Auto), expected);
+        assert_eq!(read_names(&path, NameEncoding::Gbk), expected);
+    }
+
+    #[test]
+    fn flagged_names_not_detected() {
+        let temp = TempDir::new();
+        let path = temp.path().join("flagged.zip");
+        let shift_jis = encoding_rs::SHIFT_JIS
+            .encode("テスト/データ.txt")
+            .0
+            .into_owned();
+        // 带标记的名称即使按GBK能够解码也不参与识别, 否则会掩盖未标记的日文名称
+        raw_zip(
+            &path,
+            &[
+                (shift_jis, false),
+                ("中文/文件.txt".as_bytes().to_vec(), true),
+            ],
+        );
+        assert_eq!(
+            read_names(&path, NameEncoding::Auto),
+            ["テスト/データ.txt", "中文/文件.txt"]
+        );
+    }
+
+    /// 加密条目的内容
+    const SECRET: &str = "top secret content";
+
+    ///
+    /// 写入只有一个加密条目的zip, 不压缩时内容只经过加密
+    ///
+    fn encrypted_zip(path: &Path, password: &str) {
+        let mut writer = Box::new(ZipWriter::new(
+            File::create_new(path).unwrap(),
+            ZipMethod::Stored,
+            None,
+            Some(Password::from(password)),
+        ));
+        let entry = Entry {
+            path: "secret.txt".to_string(),
+            kind: EntryKind::File,
+            size: SECRET.len() as u64,
+            ..Default::default()
+        };
+        writer.write(&entry, &mut SECRET.as_bytes()).unwrap();
+        writer.finish().unwrap();
+    }
+
+    fn open_error(path: &Path, passwords: &[&str]) -> Option<PasswordError> {
+        let passwords = passwords
+            .iter()
+            .copied()
+            .map(Password::from)
+            .collect::<Vec<_>>();
+        ZipReader::open(
+            path,
+            NameEncoding::Auto,
+            &passwords,
+            &CancelToken::default(),
+        )
+        .err()
+        .and_then(|e| e.downcast_ref::<PasswordError>().copied())
+    }
+
+    #[test]
+    fn encrypted_passwords() {
+        let temp = TempDir::new();
+        let path = temp.path().join("secret.zip");
+        encrypted_zip(&path, "right");
+        assert_eq!(open_error(&path, &[]), Some(PasswordError::Required));
+        assert_eq!(open_error(&path, &["wrong"]), Some(PasswordError::Wrong));
+        // 错误的密码之后继续尝试其余的密码
+        let reader = ZipReader::open(
+            &path,
+            NameEncoding::Auto,
+            &["wrong".into(), "right".into()],
+            &CancelToken::default(),
+        )
+        .unwrap();
+        assert_eq!(reader.password, Some(Password::from("right")));
+    }
+
+    #[test]
+    fn encrypted_round_trip() {
+        let temp = TempDir::new();
+        let path = temp.path().join("secret.zip");
+        encrypted_zip(&path, "right");
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(
+            !bytes
+                .windows(SECRET.len())
+                .any(|window| window == SECRET.as_bytes())
+        );
+        let mut contents = vec![];
+        ZipReader::open(
+            &path,
+            NameEncoding::Auto,
+            &["right".into()],
+            &CancelToken::default(),
+        )
+        .unwrap()
+        .for_each(&mut |entry, data| {
+            let mut content = String::new();
+            data.read_to_string(&mut content)?;
+            contents.push((entry.path, content));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(contents, [("secret.txt".to_string(), SECRET.to_string())]);
+    }
+}
@@ -1,62 +1,501 @@
+use anyhow::anyhow;
 use std::fmt::{Display, Formatter};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
 
 #[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum Format {
     #[default]
     Zip,
     TarGz,
+    #[cfg(feature = "bz2")]
+    TarBz2,
+    #[cfg(feature = "xz")]
+    TarXz,
+    #[cfg(feature = "zst")]
+    TarZst,
     #[cfg(feature = "7z")]
     SevenZ,
+    /// 未打包的目录或文件, 作为源时打包其中的内容, 作为目标时解压到目录中
+    Directory,
 }
 
 impl Format {
-    #[cfg(feature = "7z")]
-    pub const ALL: [Format; 3] = [Format::Zip, Format::SevenZ, Format::TarGz];
+    pub const ALL: &'static [Format] = &[
+        Format::Zip,
+        #[cfg(feature = "7z")]
+        Format::SevenZ,
+        Format::TarGz,
+        #[cfg(feature = "bz2")]
+        Format::TarBz2,
+        #[cfg(feature = "xz")]
+        Format::TarXz,
+        #[cfg(feature = "zst")]
+        Format::TarZst,
+    ];
 
-    #[cfg(not(feature = "7z"))]
-    pub const ALL: [Format; 2] = [Format::Zip, Format::TarGz];
+    ///
+    /// 可以选择的目标格式, 在所有压缩包格式之外还可以解压到目录
+    ///
+    pub const TARGETS: &'static [Format] = &[
+        Format::Zip,
+        #[cfg(feature = "7z")]
+        Format::SevenZ,
+        Format::TarGz,
+        #[cfg(feature = "bz2")]
+        Format::TarBz2,
+        #[cfg(feature = "xz")]
+        Format::TarXz,
+        #[cfg(feature = "zst")]
+        Format::TarZst,
+        Format::Directory,
+    ];
 
-    #[cfg(feature = "7z")]
-    pub fn extensions() -> [&'static str; 3] {
-        ["zip", "7z", "tar.gz"]
+    pub fn extensions() -> Vec<&'static str> {
+        Format::ALL
+            .iter()
+            .flat_map(Format::suffixes)
+            .copied()
+            .collect()
     }
 
+    ///
+    /// 标准后缀, 目录没有后缀
+    ///
     pub fn extension(&self) -> &'static str {
+        self.suffixes().first().copied().unwrap_or_default()
+    }
+
+    ///
+    /// 该格式可能使用的文件后缀, 第一个为标准后缀
+    ///
+    pub fn suffixes(&self) -> &'static [&'static str] {
         match self {
-            Format::Zip => "zip",
-            Format::TarGz => "tar.gz",
+            Format::Zip => &["zip"],
+            Format::TarGz => &["tar.gz", "tgz"],
+            #[cfg(feature = "bz2")]
+            Format::TarBz2 => &["tar.bz2", "tbz2", "tbz"],
+            #[cfg(feature = "xz")]
+            Format::TarXz => &["tar.xz", "txz"],
+            #[cfg(feature = "zst")]
+            Format::TarZst => &["tar.zst", "tzst"],
             #[cfg(feature = "7z")]
-            Format::SevenZ => "7z",
+            Format::SevenZ => &["7z"],
+            Format::Directory => &[],
         }
     }
 
-    #[cfg(not(feature = "7z"))]
-    pub fn extensions() -> [&'static str; 2] {
-        ["zip", "tar.gz"]
+    ///
+    /// 是否支持使用密码加密, zip使用AES-256, 7z使用AES-256并加密文件头
+    ///
+    pub fn encryptable(&self) -> bool {
+        match self {
+            Format::Zip => true,
+            #[cfg(feature = "7z")]
+            Format::SevenZ => true,
+            _ => false,
+        }
     }
 
+    ///
+    /// 根据文件后缀判断格式, 忽略大小写
+    ///
     pub fn parse(file_name: &str) -> Option<Format> {
-        if file_name.ends_with(".zip") {
-            return Some(Format::Zip);
+        let file_name = file_name.to_ascii_lowercase();
+        Format::ALL.iter().copied().find(|format| {
+            format
+                .suffixes()
+                .iter()
+                .any(|suffix| file_name.ends_with(&format!(".{}", suffix)))
+        })
+    }
+
+    ///
+    /// 根据文件头的魔数判断格式
+    ///
+    /// # Arguments
+    ///
+    /// * `header`: 文件开头的字节, 至少需要8个字节才能识别所有格式
+    ///
+    /// returns: Option<Format>
+    ///
+    pub fn sniff(header: &[u8]) -> Option<Format> {
+        const MAGICS: &[(&[u8], Format)] = &[
+            // 普通文件头、空压缩包和分卷压缩包
+            (b"PK\x03\x04", Format::Zip),
+            (b"PK\x05\x06", Format::Zip),
+            (b"PK\x07\x08", Format::Zip),
+            (b"\x1f\x8b", Format::TarGz),
+            #[cfg(feature = "bz2")]
+            (b"BZh", Format::TarBz2),
+            #[cfg(feature = "xz")]
+            (b"\xfd7zXZ\x00", Format::TarXz),
+            #[cfg(feature = "zst")]
+            (b"\x28\xb5\x2f\xfd", Format::TarZst),
+            #[cfg(feature = "7z")]
+            (b"7z\xbc\xaf\x27\x1c", Format::SevenZ),
+        ];
+        MAGICS
+            .iter()
+            .find(|(magic, _)| header.starts_with(magic))
+            .map(|(_, format)| *format)
+    }
+
+    ///
+    /// 去掉文件名中该格式的后缀, 没有匹配的后缀时去掉最后一个扩展名, 目录名保持不变
+    ///
+    pub fn strip_suffix<'a>(&self, file_name: &'a str) -> &'a str {
+        // 目录名中的`.`不是后缀
+        if *self == Format::Directory {
+            return file_name;
         }
-        if file_name.ends_with(".tar.gz") {
-            return Some(Format::TarGz);
+        let lowercase = file_name.to_ascii_lowercase();
+        self.suffixes()
+            .iter()
+            .find(|suffix| lowercase.ends_with(&format!(".{}", suffix)))
+            .and_then(|suffix| file_name.get(..file_name.len() - suffix.len() - 1))
+            .or_else(|| file_name.rsplit_once('.').map(|(base, _)| base))
+            .filter(|base| !base.is_empty())
+            .unwrap_or(file_name)
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Format::Directory => f.write_str("dir"),
+            _ => f.write_str(self.extension()),
         }
-        #[cfg(feature = "7z")]
-        if file_name.ends_with(".7z") {
-            return Some(Format::SevenZ);
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Format::TARGETS
+            .iter()
+            .copied()
+            .find(|format| format.to_string() == s)
+            .ok_or_else(|| anyhow!("不支持的格式: {}", s))
+    }
+}
+
+///
+/// zip条目使用的压缩方法
+///
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ZipMethod {
+    Stored,
+    #[default]
+    Deflate,
+    Bzip2,
+    Zstd,
+    /// zip库不支持写入LZMA(方法14), 使用基于LZMA2的xz(方法95)代替
+    Xz,
+}
+
+impl ZipMethod {
+    pub const ALL: [ZipMethod; 5] = [
+        ZipMethod::Stored,
+        ZipMethod::Deflate,
+        ZipMethod::Bzip2,
+        ZipMethod::Zstd,
+        ZipMethod::Xz,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ZipMethod::Stored => "stored",
+            ZipMethod::Deflate => "deflate",
+            ZipMethod::Bzip2 => "bzip2",
+            ZipMethod::Zstd => "zstd",
+            ZipMethod::Xz => "xz",
         }
-        None
     }
 }
 
-impl Display for Format {
+impl Display for ZipMethod {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ZipMethod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ZipMethod::ALL
+            .into_iter()
+            .find(|method| method.name() == s)
+            .ok_or_else(|| anyhow!("不支持的压缩方法: {}", s))
+    }
+}
+
+///
+/// zip中未设置UTF-8标记的文件名使用的编码
+///
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum NameEncoding {
+    /// 根据所有文件名的内容自动识别
+    #[default]
+    Auto,
+    Utf8,
+    /// 简体中文Windows的默认编码(CP936)
+    Gbk,
+    /// 日文Windows的默认编码(CP932)
+    ShiftJis,
+    /// zip规范中的默认编码
+    Cp437,
+}
+
+impl NameEncoding {
+    pub const ALL: [NameEncoding; 5] = [
+        NameEncoding::Auto,
+        NameEncoding::Utf8,
+        NameEncoding::Gbk,
+        NameEncoding::ShiftJis,
+        NameEncoding::Cp437,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NameEncoding::Auto => "auto",
+            NameEncoding::Utf8 => "utf-8",
+            NameEncoding::Gbk => "gbk",
+            NameEncoding::ShiftJis => "shift-jis",
+            NameEncoding::Cp437 => "cp437",
+        }
+    }
+}
+
+impl Display for NameEncoding {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Format::Zip => write!(f, "zip"),
-            Format::TarGz => write!(f, "tar.gz"),
+            NameEncoding::Auto => write!(f, "自动识别"),
+            NameEncoding::Utf8 => write!(f, "UTF-8"),
+            NameEncoding::Gbk => write!(f, "GBK"),
+            NameEncoding::ShiftJis => write!(f, "Shift-JIS"),
+            NameEncoding::Cp437 => write!(f, "CP437"),
+        }
+    }
+}
+
+impl FromStr for NameEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NameEncoding::ALL
+            .into_iter()
+            .find(|encoding| encoding.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| anyhow!("不支持的文件名编码: {}", s))
+    }
+}
+
+///
+/// 压缩级别
+///
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Level {
+    /// 使用各格式自身的默认级别
+    #[default]
+    Default,
+    Custom(u32),
+}
+
+impl Level {
+    pub fn value(&self) -> Option<u32> {
+        match self {
+            Level::Default => None,
+            Level::Custom(level) => Some(*level),
+        }
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Level::Default => write!(f, "默认"),
+            Level::Custom(level) => write!(f, "{}", level),
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Level::Default),
+            _ => s
+                .parse::<u32>()
+                .map(Level::Custom)
+                .map_err(|_| anyhow!("无效的压缩级别: {}", s)),
+        }
+    }
+}
+
+///
+/// 转换的目标格式及其压缩参数
+///
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Target {
+    pub format: Format,
+    /// zip使用的压缩方法, 其它格式忽略
+    pub method: ZipMethod,
+    pub level: Level,
+    /// 7z是否使用固实压缩, 所有文件作为一个整体压缩, 其它格式忽略
+    pub solid: bool,
+}
+
+impl Target {
+    pub fn new(format: Format) -> Self {
+        Self {
+            format,
+            ..Default::default()
+        }
+    }
+
+    ///
+    /// 当前格式和压缩方法可用的压缩级别范围, 不支持设置级别时返回None
+    ///
+    pub fn levels(&self) -> Option<RangeInclusive<u32>> {
+        match self.format {
+            Format::Zip => match self.method {
+                ZipMethod::Stored => None,
+                ZipMethod::Deflate | ZipMethod::Xz => Some(0..=9),
+                ZipMethod::Bzip2 => Some(1..=9),
+                ZipMethod::Zstd => Some(1..=22),
+            },
+            Format::TarGz => Some(0..=9),
+            #[cfg(feature = "bz2")]
+            Format::TarBz2 => Some(1..=9),
+            #[cfg(feature = "xz")]
+            Format::TarXz => Some(0..=9),
+            #[cfg(feature = "zst")]
+            Format::TarZst => Some(1..=22),
             #[cfg(feature = "7z")]
-            Format::SevenZ => write!(f, "7z"),
+            Format::SevenZ => Some(0..=9),
+            Format::Directory => None,
         }
     }
+
+    ///
+    /// 检查压缩级别是否在当前格式的范围内
+    ///
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match (self.level, self.levels()) {
+            (Level::Default, _) => Ok(()),
+            (Level::Custom(level), Some(levels)) if levels.contains(&level) => Ok(()),
+            (Level::Custom(level), Some(levels)) => Err(anyhow!(
+                "{}的压缩级别范围为{}-{}, 当前为{}",
+                self,
+                levels.start(),
+                levels.end(),
+                level
+            )),
+            (Level::Custom(_), None) => Err(anyhow!("{}不支持设置压缩级别", self)),
+        }
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.format {
+            Format::Zip => write!(f, "{}({})", self.format, self.method),
+            #[cfg(feature = "7z")]
+            Format::SevenZ if self.solid => write!(f, "{}(固实)", self.format),
+            _ => write!(f, "{}", self.format),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniff_zip() {
+        assert_eq!(
+            Format::sniff(b"PK\x03\x04\x14\x00\x00\x00"),
+            Some(Format::Zip)
+        );
+        assert_eq!(
+            Format::sniff(b"PK\x05\x06\x00\x00\x00\x00"),
+            Some(Format::Zip)
+        );
+        assert_eq!(
+            Format::sniff(b"PK\x07\x08\x00\x00\x00\x00"),
+            Some(Format::Zip)
+        );
+    }
+
+    #[test]
+    fn sniff_compressed_tar() {
+        assert_eq!(
+            Format::sniff(b"\x1f\x8b\x08\x00\x00\x00\x00\x00"),
+            Some(Format::TarGz)
+        );
+        #[cfg(feature = "bz2")]
+        assert_eq!(Format::sniff(b"BZh91AY&"), Some(Format::TarBz2));
+        #[cfg(feature = "xz")]
+        assert_eq!(Format::sniff(b"\xfd7zXZ\x00\x00\x04"), Some(Format::TarXz));
+        #[cfg(feature = "zst")]
+        assert_eq!(
+            Format::sniff(b"\x28\xb5\x2f\xfd\x00\x00"),
+            Some(Format::TarZst)
+        );
+    }
+
+    #[cfg(feature = "7z")]
+    #[test]
+    fn sniff_seven_z() {
+        assert_eq!(
+            Format::sniff(b"7z\xbc\xaf\x27\x1c\x00\x04"),
+            Some(Format::SevenZ)
+        );
+    }
+
+    #[test]
+    fn sniff_unknown() {
+        assert_eq!(Format::sniff(b""), None);
+        assert_eq!(Format::sniff(b"PK"), None);
+        assert_eq!(Format::sniff(b"\x1f"), None);
+        assert_eq!(Format::sniff(b"hello wo"), None);
+        assert_eq!(Format::sniff(b"%PDF-1.7"), None);
+    }
+
+    #[test]
+    fn parse_suffix_ignores_case() {
+        assert_eq!(Format::parse("a.zip"), Some(Format::Zip));
+        assert_eq!(Format::parse("A.ZIP"), Some(Format::Zip));
+        assert_eq!(Format::parse("a.TGZ"), Some(Format::TarGz));
+        assert_eq!(Format::parse("a.tar.gz"), Some(Format::TarGz));
+        assert_eq!(Format::parse("access.log.gz"), None);
+        assert_eq!(Format::parse("zip"), None);
+    }
+
+    #[test]
+    fn strip_matching_suffix() {
+        assert_eq!(Format::TarGz.strip_suffix("data.tar.gz"), "data");
+        assert_eq!(Format::TarGz.strip_suffix("data.tgz"), "data");
+        assert_eq!(Format::TarGz.strip_suffix("DATA.TAR.GZ"), "DATA");
+        assert_eq!(Format::Zip.strip_suffix("v1.2.zip"), "v1.2");
+    }
+
+    #[test]
+    fn strip_other_suffix() {
+        // 后缀与格式不符时只去掉最后一个扩展名
+        assert_eq!(Format::TarGz.strip_suffix("data.zip"), "data");
+        assert_eq!(Format::Zip.strip_suffix("data.tar.gz"), "data.tar");
+        assert_eq!(Format::Zip.strip_suffix("download"), "download");
+        // 去掉后缀后为空时保留原名
+        assert_eq!(Format::Zip.strip_suffix(".zip"), ".zip");
+        assert_eq!(Format::Zip.strip_suffix(".hidden"), ".hidden");
+    }
+
+    #[test]
+    fn strip_directory_name() {
+        assert_eq!(Format::Directory.strip_suffix("my.folder"), "my.folder");
+    }
 }
@@ -0,0 +1,18 @@
+//!
+//! 批量将`zip压缩包`和`tar.gz压缩包`相互转换的库, 不依赖任何界面相关的组件。
+//!
+//! 图形界面位于`gui`特性之后, 仅作为二进制程序的一部分编译。
+//!
+
+pub mod archive;
+pub mod cancel;
+pub mod codec;
+pub mod format;
+pub mod options;
+pub mod password;
+pub mod scheduler;
+pub mod status;
+#[cfg(test)]
+mod testing;
+pub mod time;
+pub mod verify;
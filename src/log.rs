@@ -0,0 +1,123 @@
+use abz::time::civil_from_days;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+///
+/// 日志级别
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LogLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Display for LogLevel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogLevel::Info => write!(f, "信息"),
+            LogLevel::Warning => write!(f, "警告"),
+            LogLevel::Error => write!(f, "错误"),
+        }
+    }
+}
+
+///
+/// 日志面板中的一条记录
+///
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub time: SystemTime,
+    pub level: LogLevel,
+    /// 单行的摘要
+    pub message: String,
+    /// 完整的错误链, 展开后显示
+    pub detail: Option<String>,
+    /// 是否已展开详情
+    pub expanded: bool,
+}
+
+impl LogEntry {
+    pub fn new(level: LogLevel, message: impl Into<String>) -> Self {
+        Self {
+            time: SystemTime::now(),
+            level,
+            message: message.into(),
+            detail: None,
+            expanded: false,
+        }
+    }
+
+    pub fn info(message: impl Into<String>) -> Self {
+        Self::new(LogLevel::Info, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(LogLevel::Warning, message)
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(LogLevel::Error, message)
+    }
+
+    ///
+    /// 处理压缩包失败的记录, 详情中保留完整的错误链
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: 压缩包路径
+    /// * `error`: 失败的原因
+    ///
+    /// returns: LogEntry
+    ///
+    pub fn failure(path: &Path, error: &anyhow::Error) -> Self {
+        Self {
+            detail: Some(format!("{:?}", error)),
+            ..Self::error(format!("{}: {}", path.display(), error))
+        }
+    }
+
+    ///
+    /// 记录产生的时间, 使用UTC时间
+    ///
+    pub fn timestamp(&self) -> String {
+        let seconds = self
+            .time
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+        let seconds = seconds % 86400;
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            seconds / 3600,
+            seconds % 3600 / 60,
+            seconds % 60
+        )
+    }
+}
+
+///
+/// 复制或导出时使用的文本格式, 包含完整的错误链
+///
+impl Display for LogEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{} UTC] [{}] {}",
+            self.timestamp(),
+            self.level,
+            self.message
+        )?;
+        if let Some(detail) = &self.detail {
+            for line in detail.lines() {
+                write!(f, "\n    {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
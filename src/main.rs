@@ -1,13 +1,50 @@
-#![windows_subsystem = "windows"]
+#![cfg_attr(feature = "gui", windows_subsystem = "windows")]
 
-use crate::app::App;
+use std::process::ExitCode;
 
+#[cfg(feature = "gui")]
 mod app;
-mod archive;
-mod format;
-mod status;
+mod cli;
+#[cfg(feature = "gui")]
+mod log;
+#[cfg(feature = "gui")]
+mod preferences;
+#[cfg(feature = "gui")]
 mod widget;
 
-fn main() -> iced::Result {
-    App::run()
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    #[cfg(feature = "gui")]
+    if args.is_empty() {
+        return match app::App::run() {
+            Ok(_) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("{}", e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+    #[cfg(all(windows, feature = "gui"))]
+    attach_console();
+    cli::run(args.into_iter())
+}
+
+///
+/// 带界面的版本在Windows下使用GUI子系统, 不会自动打开控制台, 以命令行方式运行时
+/// 附加到启动它的控制台, 否则进度和结果都不可见
+///
+#[cfg(all(windows, feature = "gui"))]
+fn attach_console() {
+    /// 附加到父进程的控制台
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+
+    // 从资源管理器等没有控制台的地方启动时会失败, 此时没有可以输出的地方, 忽略即可
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
 }
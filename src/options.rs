@@ -0,0 +1,324 @@
+use crate::password::Password;
+use anyhow::anyhow;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+///
+/// 转换选项
+///
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// 目标格式无法保存的条目类型的处理方式, 硬链接除外
+    pub unsupported: UnsupportedPolicy,
+    /// 目标格式无法保存硬链接时的处理方式, 默认转为普通文件;
+    /// 硬链接与其目标是同一个文件, 跳过时会丢失该文件的内容
+    pub hardlink: UnsupportedPolicy,
+    /// 输出文件已存在时的处理方式
+    pub conflict: ConflictPolicy,
+    /// 在输出目录中还原压缩包相对于扫描根目录的路径
+    pub mirror: bool,
+    /// 源格式与目标格式相同时直接复制文件, 不按目标的压缩参数重新压缩
+    pub copy: bool,
+    /// 转换完成后重新读取输出文件, 与源压缩包逐个条目比较
+    pub verify: bool,
+    /// 按路径筛选写入的条目
+    pub filter: Filter,
+    /// 加密输出压缩包的密码, 只有[`Format::encryptable`](crate::format::Format::encryptable)的格式支持
+    pub password: Option<Password>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            unsupported: UnsupportedPolicy::default(),
+            hardlink: UnsupportedPolicy::Materialize,
+            conflict: ConflictPolicy::default(),
+            mirror: false,
+            copy: false,
+            verify: false,
+            filter: Filter::default(),
+            password: None,
+        }
+    }
+}
+
+///
+/// 条目的包含/排除规则, 规则可以匹配完整路径或文件名, 支持`*`和`?`通配符,
+/// 匹配目录时对其中的所有条目生效
+///
+/// 设置了包含规则时只保留匹配的条目, 匹配排除规则的条目总是被去掉
+///
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Filter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl Filter {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    ///
+    /// 判断条目是否应该保留
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: 条目路径, 使用`/`分隔
+    ///
+    /// returns: bool
+    ///
+    pub fn matches(&self, path: &str) -> bool {
+        // 目录匹配时其中的所有条目同样匹配
+        let hit = |patterns: &[String]| {
+            path.match_indices('/')
+                .map(|(index, _)| &path[..index])
+                .chain(std::iter::once(path))
+                .any(|prefix| {
+                    let name = prefix.rsplit('/').next().unwrap_or(prefix);
+                    patterns
+                        .iter()
+                        .any(|pattern| wildcard(pattern, prefix) || wildcard(pattern, name))
+                })
+        };
+        (self.include.is_empty() || hit(&self.include)) && !hit(&self.exclude)
+    }
+
+    ///
+    /// 解析以逗号分隔的规则列表, 忽略空白和空规则
+    ///
+    pub fn split(patterns: &str) -> Vec<String> {
+        patterns
+            .split(',')
+            .map(str::trim)
+            .filter(|pattern| !pattern.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+///
+/// 通配符匹配, `*`匹配任意个字符, `?`匹配单个字符
+///
+fn wildcard(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    // 最近一个`*`的位置及其当前匹配到的文本位置, 用于回溯
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+///
+/// 目标格式无法保存某种条目类型(硬链接、设备文件等)时的处理方式
+///
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum UnsupportedPolicy {
+    /// 跳过该条目, 并在报告中列出
+    #[default]
+    Skip,
+    /// 链接转为其目标的普通文件, 其它类型转为空文件
+    Materialize,
+    /// 转换失败
+    Fail,
+}
+
+impl UnsupportedPolicy {
+    pub const ALL: [UnsupportedPolicy; 3] = [
+        UnsupportedPolicy::Skip,
+        UnsupportedPolicy::Materialize,
+        UnsupportedPolicy::Fail,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            UnsupportedPolicy::Skip => "skip",
+            UnsupportedPolicy::Materialize => "materialize",
+            UnsupportedPolicy::Fail => "fail",
+        }
+    }
+}
+
+impl Display for UnsupportedPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnsupportedPolicy::Skip => write!(f, "跳过"),
+            UnsupportedPolicy::Materialize => write!(f, "转为普通文件"),
+            UnsupportedPolicy::Fail => write!(f, "报错"),
+        }
+    }
+}
+
+impl FromStr for UnsupportedPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        UnsupportedPolicy::ALL
+            .into_iter()
+            .find(|policy| policy.name() == s)
+            .ok_or_else(|| anyhow!("不支持的处理方式: {}", s))
+    }
+}
+
+///
+/// 输出文件已存在时的处理方式
+///
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ConflictPolicy {
+    /// 覆盖已存在的文件
+    Overwrite,
+    /// 跳过该压缩包
+    Skip,
+    /// 自动重命名为`data (1).tar.gz`的形式
+    #[default]
+    Rename,
+    /// 询问用户, 转换时返回[`OutputExists`](crate::archive::OutputExists)错误, 由调用方决定后重新转换
+    Ask,
+}
+
+impl ConflictPolicy {
+    pub const ALL: [ConflictPolicy; 4] = [
+        ConflictPolicy::Overwrite,
+        ConflictPolicy::Skip,
+        ConflictPolicy::Rename,
+        ConflictPolicy::Ask,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConflictPolicy::Overwrite => "overwrite",
+            ConflictPolicy::Skip => "skip",
+            ConflictPolicy::Rename => "rename",
+            ConflictPolicy::Ask => "ask",
+        }
+    }
+}
+
+impl Display for ConflictPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConflictPolicy::Overwrite => write!(f, "覆盖"),
+            ConflictPolicy::Skip => write!(f, "跳过"),
+            ConflictPolicy::Rename => write!(f, "自动重命名"),
+            ConflictPolicy::Ask => write!(f, "询问"),
+        }
+    }
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ConflictPolicy::ALL
+            .into_iter()
+            .find(|policy| policy.name() == s)
+            .ok_or_else(|| anyhow!("不支持的处理方式: {}", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(include: &[&str], exclude: &[&str]) -> Filter {
+        Filter {
+            include: include.iter().map(|pattern| pattern.to_string()).collect(),
+            exclude: exclude.iter().map(|pattern| pattern.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn wildcard_literal() {
+        assert!(wildcard("a.txt", "a.txt"));
+        assert!(!wildcard("a.txt", "a.txt.bak"));
+        assert!(!wildcard("a.txt", "b.txt"));
+        assert!(wildcard("", ""));
+        assert!(!wildcard("", "a"));
+    }
+
+    #[test]
+    fn wildcard_star() {
+        assert!(wildcard("*", ""));
+        assert!(wildcard("*", "anything"));
+        assert!(wildcard("*.txt", "a.txt"));
+        assert!(wildcard("*.txt", ".txt"));
+        assert!(!wildcard("*.txt", "a.txt.bak"));
+        assert!(wildcard("a*b*c", "aXbYc"));
+        assert!(wildcard("a*b*c", "abc"));
+        assert!(!wildcard("a*b*c", "acb"));
+        // 需要回溯才能匹配
+        assert!(wildcard("*ab", "aab"));
+        assert!(wildcard("*a*a*", "banana"));
+    }
+
+    #[test]
+    fn wildcard_question_mark() {
+        assert!(wildcard("?.txt", "a.txt"));
+        assert!(!wildcard("?.txt", ".txt"));
+        assert!(!wildcard("?.txt", "ab.txt"));
+        assert!(wildcard("??", "中文"));
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        let filter = Filter::default();
+        assert!(filter.is_empty());
+        assert!(filter.matches("a/b/c.txt"));
+    }
+
+    #[test]
+    fn exclude_matches_path_or_name() {
+        let filter = filter(&[], &[".git", "*.tmp", "build/out"]);
+        assert!(!filter.matches(".git"));
+        assert!(!filter.matches(".git/config"));
+        assert!(!filter.matches("sub/.git/config"));
+        assert!(!filter.matches("sub/a.tmp"));
+        assert!(!filter.matches("build/out/a.o"));
+        assert!(filter.matches("sub/build/out/a.o"));
+        assert!(filter.matches("src/main.rs"));
+        assert!(filter.matches(".gitignore"));
+    }
+
+    #[test]
+    fn include_keeps_only_matches() {
+        let filter = filter(&["src", "*.md"], &[]);
+        assert!(filter.matches("src"));
+        assert!(filter.matches("src/main.rs"));
+        assert!(filter.matches("docs/README.md"));
+        assert!(!filter.matches("Cargo.toml"));
+        assert!(!filter.matches("tests/src.rs"));
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        let filter = filter(&["src"], &["*.bak"]);
+        assert!(filter.matches("src/main.rs"));
+        assert!(!filter.matches("src/main.rs.bak"));
+    }
+
+    #[test]
+    fn split_patterns() {
+        assert_eq!(Filter::split(" *.tmp, .git ,,"), vec!["*.tmp", ".git"]);
+        assert!(Filter::split(" , ").is_empty());
+    }
+}
@@ -0,0 +1,74 @@
+use std::fmt::{Debug, Display, Formatter};
+
+///
+/// 压缩包密码, 调试输出时隐藏内容, 避免出现在日志中
+///
+#[derive(Clone, Default, Eq, PartialEq)]
+pub struct Password(String);
+
+impl Password {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<String> for Password {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for Password {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl Debug for Password {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Password(******)")
+    }
+}
+
+///
+/// 加密的压缩包缺少密码或密码错误时返回的错误, 与压缩包损坏区分开,
+/// 可以通过`anyhow::Error::downcast_ref::<PasswordError>()`判断
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PasswordError {
+    /// 压缩包已加密, 但没有提供密码
+    Required,
+    /// 提供的密码都无法解密
+    Wrong,
+}
+
+impl Display for PasswordError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PasswordError::Required => write!(f, "压缩包已加密, 需要密码"),
+            PasswordError::Wrong => write!(f, "密码错误"),
+        }
+    }
+}
+
+impl std::error::Error for PasswordError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_hides_secret() {
+        let password = Password::from("hunter2");
+        assert!(!format!("{:?}", password).contains("hunter2"));
+        assert!(!format!("{:?}", Some(password)).contains("hunter2"));
+    }
+}
@@ -0,0 +1,245 @@
+use abz::format::{Format, Level, Target, ZipMethod};
+use abz::options::{ConflictPolicy, UnsupportedPolicy};
+use abz::scheduler::default_concurrency;
+use anyhow::anyhow;
+use iced::Size;
+use std::fs::{create_dir_all, read_to_string, rename, write};
+use std::path::PathBuf;
+
+/// 设置文件所在的目录名
+const APP_DIR: &str = "abz";
+
+/// 设置文件名
+const FILE_NAME: &str = "settings.conf";
+
+///
+/// 保存在用户配置目录中的界面设置, 每行一个`键=值`
+///
+/// 密码不会保存; 无法识别的键或值会被忽略并使用默认值, 设置文件损坏时不影响启动
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preferences {
+    pub target: Target,
+    pub recursion: bool,
+    pub concurrency: usize,
+    pub conflict: ConflictPolicy,
+    pub unsupported: UnsupportedPolicy,
+    pub hardlink: UnsupportedPolicy,
+    pub mirror: bool,
+    pub verify: bool,
+    pub copy: bool,
+    /// 上次选择文件或文件夹的目录
+    pub input_dir: Option<PathBuf>,
+    /// 上次选择的保存目录
+    pub output_dir: Option<PathBuf>,
+    pub window: Size,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            target: Target::default(),
+            recursion: true,
+            concurrency: default_concurrency(),
+            conflict: ConflictPolicy::default(),
+            unsupported: UnsupportedPolicy::default(),
+            hardlink: UnsupportedPolicy::Materialize,
+            mirror: false,
+            verify: false,
+            copy: false,
+            input_dir: None,
+            output_dir: None,
+            window: iced::window::Settings::default().size,
+        }
+    }
+}
+
+impl Preferences {
+    ///
+    /// 读取设置文件, 文件不存在或无法读取时返回默认设置
+    ///
+    pub fn load() -> Self {
+        path()
+            .and_then(|path| read_to_string(path).ok())
+            .map(|content| Self::parse(&content))
+            .unwrap_or_default()
+    }
+
+    ///
+    /// 解析设置文件的内容, 格式错误的行和无法识别的键或值会被忽略
+    ///
+    fn parse(content: &str) -> Self {
+        let mut preferences = Self::default();
+        for (key, value) in content
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim(), value.trim()))
+        {
+            // 单个值无法解析时保留默认值
+            let _ = preferences.apply(key, value);
+        }
+        preferences
+    }
+
+    fn apply(&mut self, key: &str, value: &str) -> anyhow::Result<()> {
+        match key {
+            "format" => self.target.format = value.parse::<Format>()?,
+            "method" => self.target.method = value.parse::<ZipMethod>()?,
+            "level" => self.target.level = value.parse::<Level>()?,
+            "solid" => self.target.solid = value.parse()?,
+            "recursion" => self.recursion = value.parse()?,
+            "concurrency" => {
+                self.concurrency = value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|concurrency| (1..=default_concurrency() * 2).contains(concurrency))
+                    .ok_or_else(|| anyhow!("无效的并发数量: {}", value))?
+            }
+            "conflict" => self.conflict = value.parse()?,
+            "unsupported" => self.unsupported = value.parse()?,
+            "hardlink" => self.hardlink = value.parse()?,
+            "mirror" => self.mirror = value.parse()?,
+            "verify" => self.verify = value.parse()?,
+            "copy" => self.copy = value.parse()?,
+            "input_dir" => self.input_dir = Some(PathBuf::from(value)),
+            "output_dir" => self.output_dir = Some(PathBuf::from(value)),
+            "window_width" => self.window.width = value.parse()?,
+            "window_height" => self.window.height = value.parse()?,
+            _ => {}
+        }
+        // 切换格式后可能不再支持已保存的级别
+        if self.target.validate().is_err() {
+            self.target.level = Level::Default;
+        }
+        Ok(())
+    }
+
+    ///
+    /// 写入设置文件, 先写入临时文件再重命名, 避免写入中断时损坏原有的设置
+    ///
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = path().ok_or_else(|| anyhow!("无法确定用户配置目录"))?;
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        let mut lines = vec![
+            format!("format={}", self.target.format),
+            format!("method={}", self.target.method.name()),
+            format!(
+                "level={}",
+                match self.target.level {
+                    Level::Default => "default".to_string(),
+                    Level::Custom(level) => level.to_string(),
+                }
+            ),
+            format!("solid={}", self.target.solid),
+            format!("recursion={}", self.recursion),
+            format!("concurrency={}", self.concurrency),
+            format!("conflict={}", self.conflict.name()),
+            format!("unsupported={}", self.unsupported.name()),
+            format!("hardlink={}", self.hardlink.name()),
+            format!("mirror={}", self.mirror),
+            format!("verify={}", self.verify),
+            format!("copy={}", self.copy),
+            format!("window_width={}", self.window.width),
+            format!("window_height={}", self.window.height),
+        ];
+        if let Some(dir) = &self.input_dir {
+            lines.push(format!("input_dir={}", dir.display()));
+        }
+        if let Some(dir) = &self.output_dir {
+            lines.push(format!("output_dir={}", dir.display()));
+        }
+        let temp = path.with_extension("conf.tmp");
+        write(&temp, lines.join("\n") + "\n")?;
+        rename(&temp, &path)?;
+        Ok(())
+    }
+}
+
+///
+/// 设置文件的路径: Windows为`%APPDATA%`, macOS为`~/Library/Application Support`,
+/// 其它系统为`$XDG_CONFIG_HOME`或`~/.config`
+///
+fn path() -> Option<PathBuf> {
+    let var = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+    let base = if cfg!(windows) {
+        var("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        var("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.map(|base| base.join(APP_DIR).join(FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_values() {
+        let preferences = Preferences::parse(
+            "format=tar.gz\nlevel=3\nrecursion=false\nconflict=skip\nhardlink=fail\n\
+             mirror=true\ninput_dir=/data/in\nwindow_width=800\nwindow_height=600\n",
+        );
+        assert_eq!(
+            preferences.target,
+            Target {
+                level: Level::Custom(3),
+                ..Target::new(Format::TarGz)
+            }
+        );
+        assert!(!preferences.recursion);
+        assert_eq!(preferences.conflict, ConflictPolicy::Skip);
+        assert_eq!(preferences.hardlink, UnsupportedPolicy::Fail);
+        assert!(preferences.mirror);
+        assert_eq!(preferences.input_dir, Some(PathBuf::from("/data/in")));
+        assert_eq!(preferences.output_dir, None);
+        assert_eq!(preferences.window, Size::new(800., 600.));
+    }
+
+    #[test]
+    fn ignore_malformed_lines() {
+        // 没有`=`的行、未知的键和无法解析的值都保留默认值
+        let preferences = Preferences::parse(
+            "garbage\n=\nunknown=1\nformat=rar\nrecursion=maybe\nconflict=\n  verify = true  \n",
+        );
+        assert_eq!(
+            preferences,
+            Preferences {
+                verify: true,
+                ..Preferences::default()
+            }
+        );
+        assert_eq!(Preferences::parse(""), Preferences::default());
+    }
+
+    #[test]
+    fn concurrency_out_of_range() {
+        let max = default_concurrency() * 2;
+        assert_eq!(Preferences::parse("concurrency=1").concurrency, 1);
+        assert_eq!(
+            Preferences::parse(&format!("concurrency={}", max)).concurrency,
+            max
+        );
+        for value in ["0".to_string(), (max + 1).to_string(), "-1".to_string()] {
+            assert_eq!(
+                Preferences::parse(&format!("concurrency={}", value)).concurrency,
+                default_concurrency()
+            );
+        }
+    }
+
+    #[test]
+    fn level_out_of_range_for_format() {
+        // tar.gz的级别范围为0-9
+        let preferences = Preferences::parse("format=tar.gz\nlevel=15\n");
+        assert_eq!(preferences.target, Target::new(Format::TarGz));
+        // 切换格式后已保存的级别不再适用
+        let preferences = Preferences::parse("method=zstd\nlevel=15\nformat=tar.gz\n");
+        assert_eq!(preferences.target.level, Level::Default);
+    }
+}
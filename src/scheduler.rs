@@ -0,0 +1,138 @@
+use std::collections::VecDeque;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::thread;
+
+///
+/// 批量任务调度器, 按提交顺序(FIFO)执行任务, 同时执行的任务数不超过并发上限
+///
+#[derive(Debug, Copy, Clone)]
+pub struct Scheduler {
+    concurrency: usize,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new(default_concurrency())
+    }
+}
+
+impl Scheduler {
+    ///
+    /// 创建调度器
+    ///
+    /// # Arguments
+    ///
+    /// * `concurrency`: 并发上限, 小于1时按1处理
+    ///
+    /// returns: Scheduler
+    ///
+    pub fn new(concurrency: usize) -> Self {
+        Self {
+            concurrency: concurrency.max(1),
+        }
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    ///
+    /// 执行所有任务, 全部完成后按提交顺序返回结果
+    ///
+    /// # Arguments
+    ///
+    /// * `jobs`: 任务列表
+    /// * `f`: 执行任务的函数, 参数为任务的序号和任务本身
+    ///
+    /// returns: Vec<R>
+    ///
+    pub fn run<T, R, F>(&self, jobs: Vec<T>, f: F) -> Vec<R>
+    where
+        T: Send,
+        R: Send,
+        F: Fn(usize, T) -> R + Sync,
+    {
+        let total = jobs.len();
+        let queue = Mutex::new(jobs.into_iter().enumerate().collect::<VecDeque<_>>());
+        let results = Mutex::new((0..total).map(|_| None).collect::<Vec<_>>());
+        thread::scope(|scope| {
+            for _ in 0..self.concurrency.min(total) {
+                scope.spawn(|| {
+                    loop {
+                        // 取出任务后立即释放锁, 避免阻塞其它工作线程
+                        let job = queue.lock().expect("lock job queue error").pop_front();
+                        let Some((index, job)) = job else {
+                            break;
+                        };
+                        let result = f(index, job);
+                        results.lock().expect("lock job results error")[index] = Some(result);
+                    }
+                });
+            }
+        });
+        results
+            .into_inner()
+            .expect("lock job results error")
+            .into_iter()
+            .map(|result| result.expect("job not finished"))
+            .collect()
+    }
+}
+
+///
+/// 默认的并发上限, 与CPU的逻辑核心数一致
+///
+pub fn default_concurrency() -> usize {
+    thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn start_in_fifo_order() {
+        let started = Mutex::new(vec![]);
+        let results = Scheduler::new(1).run((0..8).collect(), |index, job: usize| {
+            started.lock().unwrap().push(index);
+            job * 10
+        });
+        assert_eq!(started.into_inner().unwrap(), (0..8).collect::<Vec<_>>());
+        assert_eq!(results, (0..8).map(|job| job * 10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn results_in_submission_order() {
+        // 先提交的任务耗时更长, 完成顺序与提交顺序相反
+        let results = Scheduler::new(4).run((0..4).collect(), |index, job: u64| {
+            thread::sleep(Duration::from_millis(40 - job * 10));
+            index
+        });
+        assert_eq!(results, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn bounded_concurrency() {
+        let running = AtomicUsize::new(0);
+        let watermark = AtomicUsize::new(0);
+        Scheduler::new(3).run((0..12).collect(), |_, _: usize| {
+            let current = running.fetch_add(1, Ordering::SeqCst) + 1;
+            watermark.fetch_max(current, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            running.fetch_sub(1, Ordering::SeqCst);
+        });
+        assert_eq!(watermark.into_inner(), 3);
+    }
+
+    #[test]
+    fn zero_concurrency_runs_serially() {
+        let scheduler = Scheduler::new(0);
+        assert_eq!(scheduler.concurrency(), 1);
+        assert_eq!(scheduler.run(vec![1, 2, 3], |_, job| job + 1), [2, 3, 4]);
+    }
+}
@@ -1,27 +1,45 @@
-use iced::Color;
+use crate::archive::Report;
 use std::cmp::Ordering;
 use std::fmt::{Display, Formatter};
 use std::sync::Arc;
 
-#[derive(Default, Debug, Clone, PartialEq, PartialOrd)]
+#[derive(Default, Debug, Clone, PartialEq)]
 pub enum Status {
     #[default]
     Pending,
     Processing(f32),
+    /// 校验输出文件
+    Verifying(f32),
     Success,
+    /// 处理成功, 但存在需要提示的问题
+    Warning(Arc<str>),
     Failed(Arc<str>),
+    /// 被用户取消
+    Cancelled,
+    /// 未处理, 例如输出文件已存在
+    Skipped(Arc<str>),
 }
 
 impl Eq for Status {}
 
+impl PartialOrd for Status {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Ord for Status {
     fn cmp(&self, other: &Self) -> Ordering {
         fn ordinal(status: &Status) -> usize {
             match status {
                 Status::Pending => 0,
                 Status::Processing(_) => 1,
-                Status::Success => 2,
-                Status::Failed(_) => 3,
+                Status::Verifying(_) => 2,
+                Status::Success => 3,
+                Status::Warning(_) => 4,
+                Status::Failed(_) => 5,
+                Status::Cancelled => 6,
+                Status::Skipped(_) => 7,
             }
         }
 
@@ -29,7 +47,8 @@ impl Ord for Status {
         let o2 = ordinal(other);
         if o1 == o2 {
             match (self, other) {
-                (Self::Processing(a), Self::Processing(b)) => {
+                (Self::Processing(a), Self::Processing(b))
+                | (Self::Verifying(a), Self::Verifying(b)) => {
                     a.partial_cmp(b).unwrap_or(Ordering::Equal)
                 }
                 _ => Ordering::Equal,
@@ -40,24 +59,26 @@ impl Ord for Status {
     }
 }
 
-impl Status {
-    pub fn color(&self) -> Color {
-        match self {
-            Status::Pending => Color::from_rgb(0.42, 0.45, 0.50), // Gray (#6b7280)
-            Status::Processing(_) => Color::from_rgb(0.15, 0.39, 0.92), // Blue (#2563eb)
-            Status::Success => Color::from_rgb(0.09, 0.64, 0.29), // Green (#16a34a)
-            Status::Failed(_) => Color::from_rgb(0.86, 0.15, 0.15), // Red (#dc2626)
-        }
-    }
-}
-
 impl Display for Status {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Status::Pending => write!(f, "等待处理"),
             Status::Processing(_) => write!(f, "处理中"),
+            Status::Verifying(_) => write!(f, "校验中"),
             Status::Success => write!(f, "处理成功"),
+            Status::Warning(e) => write!(f, "处理成功: {}", e),
             Status::Failed(e) => f.write_str(&format!("处理失败: {}", e)),
+            Status::Cancelled => write!(f, "已取消"),
+            Status::Skipped(reason) => write!(f, "已跳过: {}", reason),
+        }
+    }
+}
+
+impl From<&Report> for Status {
+    fn from(report: &Report) -> Self {
+        match report.is_empty() {
+            true => Status::Success,
+            false => Status::Warning(report.to_string().into()),
         }
     }
 }
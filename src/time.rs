@@ -0,0 +1,70 @@
+//!
+//! 公历日期与距1970-01-01天数的相互换算, 不依赖时区, 按UTC处理
+//!
+//! 参考 <http://howardhinnant.github.io/date_algorithms.html>
+//!
+
+///
+/// 公历日期转为距1970-01-01的天数
+///
+/// # Arguments
+///
+/// * `year`: 年
+/// * `month`: 月, 1-12
+/// * `day`: 日, 1-31
+///
+/// returns: i64 早于1970-01-01时为负数
+///
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+///
+/// 距1970-01-01的天数转为公历日期
+///
+/// # Arguments
+///
+/// * `days`: 距1970-01-01的天数, 可以为负数
+///
+/// returns: (i64, i64, i64) 依次为年、月、日
+///
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(3652), (1980, 1, 1));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(days_from_civil(2107, 12, 31), 50402);
+    }
+
+    #[test]
+    fn round_trip() {
+        for days in (-800_000..800_000).step_by(97) {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+}
@@ -0,0 +1,458 @@
+use crate::archive::ProcessRead;
+use crate::cancel::CancelToken;
+use crate::codec::{ArchiveReader, ArchiveWriter, Attribute, Entry, EntryKind};
+use anyhow::Context;
+use crc32fast::Hasher;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Read;
+
+///
+/// 条目内容的摘要
+///
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Digest {
+    pub kind: EntryKind,
+    /// 内容的字节数
+    pub size: u64,
+    pub crc32: u32,
+    /// 链接的目标路径
+    pub link: Option<String>,
+}
+
+///
+/// 压缩包中所有条目的摘要, 以条目路径为键
+///
+#[derive(Debug, Clone, Default)]
+pub struct Manifest(BTreeMap<String, Digest>);
+
+impl Manifest {
+    ///
+    /// 添加条目, 路径中的`.`和多余的`/`会被去掉, 根目录本身不会记录
+    ///
+    pub fn insert(&mut self, path: &str, digest: Digest) {
+        let path = normalize(path);
+        if !path.is_empty() {
+            self.0.insert(path, digest);
+        }
+    }
+
+    ///
+    /// 所有条目内容的总大小
+    ///
+    pub fn size(&self) -> u64 {
+        self.0.values().map(|digest| digest.size).sum()
+    }
+
+    ///
+    /// 与另一个清单比较, 返回所有差异的描述
+    ///
+    /// # Arguments
+    ///
+    /// * `actual`: 实际读取到的清单
+    ///
+    /// returns: Vec<String>
+    ///
+    pub fn diff(&self, actual: &Manifest) -> Vec<String> {
+        let mut differences = vec![];
+        // 同一份数据的多个硬链接解压到目录后重新读取时, 哪个路径作为普通文件取决于遍历顺序,
+        // 因此文件和硬链接按共享数据的分组比较: 分组成员一致, 且分组的数据一致
+        let linked = |kind| matches!(kind, EntryKind::File | EntryKind::Hardlink);
+        let (expected_groups, actual_groups) = (self.groups(), actual.groups());
+        for (path, expected) in &self.0 {
+            let Some(actual_digest) = actual.0.get(path) else {
+                differences.push(format!("缺少条目: {}", path));
+                continue;
+            };
+            if linked(expected.kind) && linked(actual_digest.kind) {
+                let (expected_root, actual_root) = (self.root(path), actual.root(path));
+                if expected_groups.get(&expected_root) != actual_groups.get(&actual_root) {
+                    differences.push(format!("硬链接不一致: {}", path));
+                    continue;
+                }
+                // 每个分组只在持有数据的路径上比较一次内容
+                if *path != expected_root {
+                    continue;
+                }
+                let Some(actual_data) = actual.0.get(&actual_root) else {
+                    continue;
+                };
+                if expected.size != actual_data.size {
+                    differences.push(format!(
+                        "大小不一致: {} ({} -> {})",
+                        path, expected.size, actual_data.size
+                    ));
+                } else if expected.crc32 != actual_data.crc32 {
+                    differences.push(format!("内容不一致: {}", path));
+                }
+            } else if expected.kind != actual_digest.kind {
+                differences.push(format!(
+                    "类型不一致: {} ({} -> {})",
+                    path, expected.kind, actual_digest.kind
+                ));
+            } else if expected.link != actual_digest.link {
+                differences.push(format!("链接目标不一致: {}", path));
+            } else if expected.size != actual_digest.size {
+                differences.push(format!(
+                    "大小不一致: {} ({} -> {})",
+                    path, expected.size, actual_digest.size
+                ));
+            } else if expected.crc32 != actual_digest.crc32 {
+                differences.push(format!("内容不一致: {}", path));
+            }
+        }
+        differences.extend(
+            actual
+                .0
+                .iter()
+                // 解压到目录时会自动创建压缩包中没有单独记录的上级目录
+                .filter(|(path, digest)| {
+                    !self.0.contains_key(*path) && digest.kind != EntryKind::Directory
+                })
+                .map(|(path, _)| format!("多出条目: {}", path)),
+        );
+        differences
+    }
+
+    ///
+    /// 沿硬链接找到持有数据的路径, 普通文件就是其自身
+    ///
+    fn root(&self, path: &str) -> String {
+        let mut path = path.to_string();
+        // 限制跟随次数, 避免损坏的压缩包中硬链接互相指向时死循环
+        for _ in 0..=self.0.len() {
+            match self.0.get(&path) {
+                Some(Digest {
+                    kind: EntryKind::Hardlink,
+                    link: Some(link),
+                    ..
+                }) => path = normalize(link),
+                _ => break,
+            }
+        }
+        path
+    }
+
+    ///
+    /// 按持有数据的路径对文件和硬链接分组
+    ///
+    fn groups(&self) -> BTreeMap<String, BTreeSet<&str>> {
+        let mut groups = BTreeMap::<_, BTreeSet<_>>::new();
+        for (path, digest) in &self.0 {
+            if matches!(digest.kind, EntryKind::File | EntryKind::Hardlink) {
+                groups
+                    .entry(self.root(path))
+                    .or_default()
+                    .insert(path.as_str());
+            }
+        }
+        groups
+    }
+}
+
+///
+/// 去掉路径中的`.`和多余的`/`
+///
+fn normalize(path: &str) -> String {
+    path.split('/')
+        .filter(|component| !component.is_empty() && *component != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+///
+/// 计算读取内容的CRC32和字节数
+///
+pub struct Checksum<R> {
+    read: R,
+    hasher: Hasher,
+    size: u64,
+}
+
+impl<R> Checksum<R> {
+    pub fn new(read: R) -> Self {
+        Self {
+            read,
+            hasher: Hasher::new(),
+            size: 0,
+        }
+    }
+
+    ///
+    /// 生成条目的摘要
+    ///
+    pub fn digest(self, entry: &Entry) -> Digest {
+        Digest {
+            kind: entry.kind,
+            size: self.size,
+            crc32: self.hasher.finalize(),
+            link: entry.link.clone(),
+        }
+    }
+}
+
+impl<R: Read> Read for Checksum<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let size = self.read.read(buf)?;
+        self.hasher.update(&buf[..size]);
+        self.size += size as u64;
+        Ok(size)
+    }
+}
+
+///
+/// 在写入的同时记录每个条目摘要的写入器, 普通文件的大小和CRC优先使用源格式中保存的值
+///
+pub struct Recorder {
+    writer: Box<dyn ArchiveWriter>,
+    manifest: Manifest,
+}
+
+impl Recorder {
+    pub fn new(writer: Box<dyn ArchiveWriter>) -> Self {
+        Self {
+            writer,
+            manifest: Manifest::default(),
+        }
+    }
+
+    ///
+    /// 完成写入, 返回记录的清单
+    ///
+    pub fn close(self) -> anyhow::Result<Manifest> {
+        self.writer.finish()?;
+        Ok(self.manifest)
+    }
+}
+
+impl ArchiveWriter for Recorder {
+    fn attributes(&self) -> &'static [Attribute] {
+        self.writer.attributes()
+    }
+
+    fn kinds(&self) -> &'static [EntryKind] {
+        self.writer.kinds()
+    }
+
+    fn dropped(&self, entry: &Entry) -> Vec<Attribute> {
+        self.writer.dropped(entry)
+    }
+
+    fn write(&mut self, entry: &Entry, data: &mut dyn Read) -> anyhow::Result<()> {
+        let mut checksum = Checksum::new(data);
+        self.writer.write(entry, &mut checksum)?;
+        let mut digest = checksum.digest(entry);
+        // 以源格式声明的大小和CRC为准, 源数据被截断或损坏时与输出不一致, 而不是原样通过校验
+        if entry.kind == EntryKind::File {
+            digest.size = entry.size;
+            digest.crc32 = entry.crc32.unwrap_or(digest.crc32);
+        }
+        self.manifest.insert(&entry.path, digest);
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> anyhow::Result<()> {
+        self.writer.finish()
+    }
+}
+
+///
+/// 读取压缩包的所有条目并生成清单, 条目损坏时返回的错误中包含条目路径
+///
+/// # Arguments
+///
+/// * `reader`: 压缩包
+/// * `cancel`: 取消标记
+/// * `process`: 每次读取的字节数
+///
+/// returns: Result<Manifest, Error>
+///
+pub fn scan(
+    reader: &mut dyn ArchiveReader,
+    cancel: &CancelToken,
+    process: &mut dyn FnMut(u64),
+) -> anyhow::Result<Manifest> {
+    let mut manifest = Manifest::default();
+    reader.for_each(&mut |entry, data| {
+        let mut checksum = Checksum::new(ProcessRead::new(data, cancel, &mut *process));
+        std::io::copy(&mut checksum, &mut std::io::sink())
+            .with_context(|| format!("条目已损坏: {}", entry.path))?;
+        manifest.insert(&entry.path, checksum.digest(&entry));
+        Ok(())
+    })?;
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec;
+    use crate::format::{Format, NameEncoding, Target, ZipMethod};
+    use crate::testing::TempDir;
+    use std::path::Path;
+
+    fn file(path: &str, size: u64, crc32: Option<u32>) -> Entry {
+        Entry {
+            path: path.to_string(),
+            kind: EntryKind::File,
+            size,
+            crc32,
+            ..Default::default()
+        }
+    }
+
+    ///
+    /// 通过[Recorder]写入条目, 返回写入时记录的清单
+    ///
+    fn record(target: Target, output: &Path, entries: &[(Entry, &str)]) -> Manifest {
+        let mut recorder = Recorder::new(codec::writer(&target, output, None).unwrap());
+        for (entry, content) in entries {
+            recorder.write(entry, &mut content.as_bytes()).unwrap();
+        }
+        recorder.close().unwrap()
+    }
+
+    fn rescan(format: Format, output: &Path) -> anyhow::Result<Manifest> {
+        let mut reader = codec::reader(
+            format,
+            output,
+            NameEncoding::Utf8,
+            &[],
+            &CancelToken::default(),
+        )?;
+        scan(&mut *reader, &CancelToken::default(), &mut |_| {})
+    }
+
+    #[test]
+    fn matching_output() {
+        let temp = TempDir::new();
+        let output = temp.path().join("out.zip");
+        let expected = record(
+            Target::new(Format::Zip),
+            &output,
+            &[
+                (file("a.txt", 5, Some(crc32fast::hash(b"hello"))), "hello"),
+                (file("b.txt", 0, None), ""),
+            ],
+        );
+        let actual = rescan(Format::Zip, &output).unwrap();
+        assert!(expected.diff(&actual).is_empty());
+    }
+
+    #[test]
+    fn hardlink_group_swapped() {
+        let digest = |kind, data: &[u8], link: Option<&str>| Digest {
+            kind,
+            size: data.len() as u64,
+            crc32: crc32fast::hash(data),
+            link: link.map(str::to_string),
+        };
+        let mut expected = Manifest::default();
+        expected.insert("z.txt", digest(EntryKind::File, b"hello", None));
+        expected.insert("a.txt", digest(EntryKind::Hardlink, b"", Some("z.txt")));
+        expected.insert("m.txt", digest(EntryKind::Hardlink, b"", Some("./z.txt")));
+        let mut actual = Manifest::default();
+        actual.insert("a.txt", digest(EntryKind::File, b"hello", None));
+        actual.insert("m.txt", digest(EntryKind::Hardlink, b"", Some("a.txt")));
+        actual.insert("z.txt", digest(EntryKind::Hardlink, b"", Some("a.txt")));
+        assert!(expected.diff(&actual).is_empty());
+
+        // 数据持有者换了路径时仍然比较内容
+        actual.insert("a.txt", digest(EntryKind::File, b"hellx", None));
+        assert_eq!(
+            expected.diff(&actual),
+            vec!["内容不一致: z.txt".to_string()]
+        );
+
+        // 分组成员不一致
+        actual.insert("a.txt", digest(EntryKind::File, b"hello", None));
+        actual.insert("m.txt", digest(EntryKind::File, b"hello", None));
+        assert_eq!(
+            expected.diff(&actual),
+            vec![
+                "硬链接不一致: a.txt".to_string(),
+                "硬链接不一致: m.txt".to_string(),
+                "硬链接不一致: z.txt".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated_source() {
+        let temp = TempDir::new();
+        let output = temp.path().join("out");
+        // 源格式声明了5个字节, 实际只读到3个字节
+        let expected = record(
+            Target::new(Format::Directory),
+            &output,
+            &[(file("a.txt", 5, None), "hel")],
+        );
+        let actual = rescan(Format::Directory, &output).unwrap();
+        assert_eq!(expected.diff(&actual), ["大小不一致: a.txt (5 -> 3)"]);
+    }
+
+    #[test]
+    fn corrupted_source() {
+        let temp = TempDir::new();
+        let output = temp.path().join("out");
+        // 读到的内容与源格式保存的CRC不符
+        let expected = record(
+            Target::new(Format::Directory),
+            &output,
+            &[(file("a.txt", 5, Some(crc32fast::hash(b"hello"))), "hallo")],
+        );
+        let actual = rescan(Format::Directory, &output).unwrap();
+        assert_eq!(expected.diff(&actual), ["内容不一致: a.txt"]);
+    }
+
+    #[test]
+    fn corrupted_output() {
+        let temp = TempDir::new();
+        let output = temp.path().join("out");
+        let expected = record(
+            Target::new(Format::Directory),
+            &output,
+            &[
+                (file("a.txt", 5, None), "hello"),
+                (file("b.txt", 5, None), "world"),
+                (file("c.txt", 1, None), "c"),
+            ],
+        );
+        // 写入完成后修改输出的内容
+        std::fs::write(output.join("a.txt"), "hallo").unwrap();
+        std::fs::write(output.join("b.txt"), "wor").unwrap();
+        std::fs::remove_file(output.join("c.txt")).unwrap();
+        std::fs::write(output.join("d.txt"), "d").unwrap();
+        let actual = rescan(Format::Directory, &output).unwrap();
+        assert_eq!(
+            expected.diff(&actual),
+            [
+                "内容不一致: a.txt",
+                "大小不一致: b.txt (5 -> 3)",
+                "缺少条目: c.txt",
+                "多出条目: d.txt",
+            ]
+        );
+    }
+
+    #[test]
+    fn corrupted_zip_output() {
+        let temp = TempDir::new();
+        let output = temp.path().join("out.zip");
+        let target = Target {
+            method: ZipMethod::Stored,
+            ..Target::new(Format::Zip)
+        };
+        let content = "verification content";
+        record(target, &output, &[(file("a.txt", 20, None), content)]);
+        // 不压缩时内容原样保存, 修改其中一个字节后zip保存的CRC不再匹配
+        let mut bytes = std::fs::read(&output).unwrap();
+        let offset = bytes
+            .windows(content.len())
+            .position(|window| window == content.as_bytes())
+            .unwrap();
+        bytes[offset] ^= 0xff;
+        std::fs::write(&output, bytes).unwrap();
+        let error = rescan(Format::Zip, &output).unwrap_err();
+        assert!(format!("{:#}", error).contains("a.txt"));
+    }
+}
@@ -0,0 +1,158 @@
+use crate::app::Message;
+use crate::widget::icon::icon;
+use abz::archive::Archive;
+use abz::format::{Format, NameEncoding};
+use abz::status::Status;
+use human_bytes::human_bytes;
+use iced::widget::text::Wrapping;
+use iced::widget::{
+    button, container, horizontal_space, hover, pick_list, progress_bar, row, text,
+};
+use iced::{Alignment, Border, Color, Element, Length, Theme};
+use lucide_rs::Lucide;
+use std::borrow::Borrow;
+
+const WARNING_COLOR: Color = Color::from_rgb(0.85, 0.47, 0.02); // Amber (#d97706)
+
+///
+/// 压缩包列表中的一行
+///
+/// # Arguments
+///
+/// * `archive`: 压缩包
+/// * `index`: 在列表中的位置
+/// * `loading`: 是否正在转换, 转换期间只能取消等待中或处理中的压缩包, 不能删除,
+///   目标格式和文件名编码只显示不能修改, 修改不会影响正在进行的批次
+/// * `target`: 整批的目标格式, 没有单独指定目标格式时显示该格式
+///
+/// returns: Element<Message>
+///
+pub fn archive(
+    archive: &Archive,
+    index: usize,
+    loading: bool,
+    target: Format,
+) -> Element<'_, Message> {
+    let mut details = row![
+        text(human_bytes(archive.size as f64))
+            .width(Length::Fixed(60.))
+            .wrapping(Wrapping::WordOrGlyph),
+        text(archive.status.to_string())
+            .color(status_color(&archive.status))
+            .width(Length::Fill),
+        text("目标格式:"),
+        option(
+            loading,
+            Format::TARGETS,
+            archive.target.unwrap_or(target),
+            move |format| Message::UpdateArchiveTarget(index, format),
+        )
+    ]
+    .align_y(Alignment::Center)
+    .spacing(5);
+    // 只有zip的文件名可能不是UTF-8, 识别错误时可以手动指定编码
+    if archive.format == Format::Zip {
+        details = details.push(text("文件名编码:")).push(option(
+            loading,
+            NameEncoding::ALL,
+            archive.encoding,
+            move |encoding| Message::UpdateArchiveEncoding(index, encoding),
+        ));
+    }
+    if let Some(labeled) = archive.mislabeled {
+        details = details.push(
+            text(format!("后缀为{}, 实际内容为{}", labeled, archive.format)).color(WARNING_COLOR),
+        );
+    }
+    let base = container(
+        row![
+            iced::widget::column![text(archive.path.display().to_string()), details].spacing(5),
+            match archive.status {
+                Status::Processing(ratio) | Status::Verifying(ratio) => Element::from(
+                    row![
+                        progress_bar(0.0..=1.0, ratio)
+                            .length(Length::Fixed(100.))
+                            .girth(Length::Fixed(5.)),
+                        text(format!("{:.02}%", ratio * 100.)).width(Length::Fixed(30.))
+                    ]
+                    .align_y(Alignment::Center)
+                    .spacing(5),
+                ),
+                Status::Success | Status::Warning(_) => Element::from(
+                    row![
+                        progress_bar(0.0..=1.0, 1.0)
+                            .length(Length::Fixed(100.))
+                            .girth(Length::Fixed(5.))
+                            .style(progress_bar::success),
+                        text("100%").width(Length::Fixed(30.)).style(text::success)
+                    ]
+                    .align_y(Alignment::Center)
+                    .spacing(5),
+                ),
+                _ => horizontal_space().width(Length::Fixed(130.)).into(),
+            },
+            horizontal_space().width(Length::Fixed(40.))
+        ]
+        .spacing(20)
+        .align_y(Alignment::Center),
+    )
+    .padding(5)
+    .width(Length::Fill)
+    .align_x(Alignment::Start)
+    .align_y(Alignment::Center)
+    .style(|theme: &Theme| {
+        container::transparent(theme).border(
+            Border::default()
+                .width(1)
+                .rounded(3)
+                .color(theme.extended_palette().secondary.weak.color),
+        )
+    });
+    let action = match (loading, &archive.status) {
+        (false, _) => button(icon(Lucide::Trash)).on_press(Message::RemoveArchive(index)),
+        (true, Status::Pending | Status::Processing(_) | Status::Verifying(_)) => {
+            button(icon(Lucide::X)).on_press(Message::CancelArchive(index))
+        }
+        (true, _) => return base.into(),
+    };
+    let top = container(action)
+        .padding([0, 15])
+        .align_x(Alignment::End)
+        .align_y(Alignment::Center)
+        .width(Length::Fill)
+        .height(Length::Fill);
+    hover(base, top)
+}
+
+///
+/// 行内的选择框, 转换期间只显示当前选中的值
+///
+fn option<'a, T>(
+    loading: bool,
+    options: impl Borrow<[T]> + 'a,
+    selected: T,
+    on_select: impl Fn(T) -> Message + 'a,
+) -> Element<'a, Message>
+where
+    T: ToString + PartialEq + Clone + 'a,
+{
+    match loading {
+        true => text(selected.to_string()).size(12).into(),
+        false => pick_list(options, Some(selected), on_select)
+            .text_size(12)
+            .padding([2, 5])
+            .into(),
+    }
+}
+
+fn status_color(status: &Status) -> Color {
+    match status {
+        Status::Pending => Color::from_rgb(0.42, 0.45, 0.50), // Gray (#6b7280)
+        Status::Processing(_) => Color::from_rgb(0.15, 0.39, 0.92), // Blue (#2563eb)
+        Status::Verifying(_) => Color::from_rgb(0.49, 0.23, 0.93), // Violet (#7c3aed)
+        Status::Success => Color::from_rgb(0.09, 0.64, 0.29), // Green (#16a34a)
+        Status::Warning(_) => WARNING_COLOR,
+        Status::Failed(_) => Color::from_rgb(0.86, 0.15, 0.15), // Red (#dc2626)
+        Status::Cancelled | Status::Skipped(_) => Color::from_rgb(0.39, 0.45, 0.55), // Slate (#64748b)
+    }
+}
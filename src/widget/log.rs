@@ -0,0 +1,97 @@
+use crate::app::Message;
+use crate::log::{LogEntry, LogLevel};
+use crate::widget::button::icon_button;
+use crate::widget::icon::icon;
+use iced::widget::text::Wrapping;
+use iced::widget::{
+    Column, button, center, column, container, horizontal_space, row, scrollable, text,
+};
+use iced::{Alignment, Color, Element, Length};
+use lucide_rs::Lucide;
+
+///
+/// 日志面板, 最新的记录显示在最上方
+///
+/// # Arguments
+///
+/// * `logs`: 按时间顺序排列的记录
+///
+/// returns: Element<Message>
+///
+pub fn log_panel(logs: &[LogEntry]) -> Element<'_, Message> {
+    let header = row![
+        icon(Lucide::ScrollText),
+        text("日志"),
+        horizontal_space(),
+        icon_button(Lucide::Copy, "复制全部")
+            .on_press_maybe((!logs.is_empty()).then_some(Message::CopyLog(None))),
+        icon_button(Lucide::Upload, "导出")
+            .on_press_maybe((!logs.is_empty()).then_some(Message::ExportLog)),
+        icon_button(Lucide::Trash, "清空")
+            .on_press_maybe((!logs.is_empty()).then_some(Message::ClearLog)),
+    ]
+    .spacing(10)
+    .align_y(Alignment::Center);
+    let body = if logs.is_empty() {
+        Element::from(center(text("暂无日志")))
+    } else {
+        scrollable(
+            Column::with_children(
+                logs.iter()
+                    .enumerate()
+                    .rev()
+                    .map(|(index, entry)| log_entry(entry, index)),
+            )
+            .spacing(5),
+        )
+        .spacing(10)
+        .into()
+    };
+    column![header, body]
+        .spacing(10)
+        .height(Length::Fixed(200.))
+        .into()
+}
+
+///
+/// 单条日志, 带有错误链的记录可以展开查看
+///
+fn log_entry(entry: &LogEntry, index: usize) -> Element<'_, Message> {
+    let mut summary = row![
+        text(entry.timestamp()).color(Color::from_rgb(0.42, 0.45, 0.50)), // Gray (#6b7280)
+        text(entry.level.to_string()).color(level_color(entry.level)),
+        text(&entry.message)
+            .width(Length::Fill)
+            .wrapping(Wrapping::WordOrGlyph),
+    ]
+    .spacing(10)
+    .align_y(Alignment::Center);
+    if entry.detail.is_some() {
+        summary = summary.push(
+            button(icon(if entry.expanded {
+                Lucide::EyeOff
+            } else {
+                Lucide::Eye
+            }))
+            .on_press(Message::ToggleLogEntry(index)),
+        );
+    }
+    summary = summary.push(button(icon(Lucide::Copy)).on_press(Message::CopyLog(Some(index))));
+    match (&entry.detail, entry.expanded) {
+        (Some(detail), true) => column![
+            summary,
+            container(text(detail).wrapping(Wrapping::WordOrGlyph)).padding([0, 20])
+        ]
+        .spacing(5)
+        .into(),
+        _ => summary.into(),
+    }
+}
+
+fn level_color(level: LogLevel) -> Color {
+    match level {
+        LogLevel::Info => Color::from_rgb(0.15, 0.39, 0.92), // Blue (#2563eb)
+        LogLevel::Warning => Color::from_rgb(0.85, 0.47, 0.02), // Amber (#d97706)
+        LogLevel::Error => Color::from_rgb(0.86, 0.15, 0.15), // Red (#dc2626)
+    }
+}
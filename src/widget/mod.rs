@@ -1,2 +1,4 @@
+pub mod archive;
 pub mod button;
 pub mod icon;
+pub mod log;